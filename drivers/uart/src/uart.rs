@@ -5,7 +5,7 @@
 use core::cmp;
 use core::fmt::{self, Write};
 use core::ptr::{read_volatile, write_volatile};
-use spin::Once;
+use sync::OnceCell;
 
 use fdt::node::FdtNode;
 
@@ -44,6 +44,11 @@ impl Config {
         })
     }
 
+    // 以新的基址访问同一组寄存器, 例如内核开启分页后改用直接映射地址
+    pub const fn with_base(self, base: usize) -> Self {
+        Self { base, ..self }
+    }
+
     pub const fn base(&self) -> usize {
         self.base
     }
@@ -87,14 +92,20 @@ impl Uart {
 
 struct UartWriter<'a>(&'a Uart);
 
-impl<'a> Write for UartWriter<'a> {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for ch in s.bytes() {
-            if ch == b'\n' {
+impl UartWriter<'_> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if b == b'\n' {
                 self.0.putb(b'\r');
             }
-            self.0.putb(ch);
+            self.0.putb(b);
         }
+    }
+}
+
+impl<'a> Write for UartWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
         Ok(())
     }
     fn write_char(&mut self, c: char) -> fmt::Result {
@@ -122,10 +133,10 @@ pub const DEFAULT_QEMU_VIRT: Config = Config::new(
     0x20,        // LSR.THRE
 );
 
-static UART: Once<Uart> = Once::new();
+static UART: OnceCell<Uart> = OnceCell::new();
 
 pub fn init(cfg: Config) {
-    UART.call_once(|| Uart::from_config(cfg));
+    UART.get_or_init(|| Uart::from_config(cfg));
 }
 
 #[doc(hidden)]
@@ -135,6 +146,13 @@ pub fn _print(args: fmt::Arguments) {
     }
 }
 
+// 原样输出字节, 供内核注册为控制台; 尚未初始化时丢弃
+pub fn write_bytes(bytes: &[u8]) {
+    if let Some(uart) = UART.get() {
+        UartWriter(uart).write_bytes(bytes);
+    }
+}
+
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => {{
@@ -1,5 +1,54 @@
+use std::env;
+
+// hart id 上限, 可以通过环境变量 GLENDA_MAX_HARTS 覆盖
+const DEFAULT_MAX_HARTS: usize = 64;
+// 栈窗口为 1 GiB, 每个 hart 占 32 KiB
+const MAX_HARTS_LIMIT: usize = 32768;
+// 符号表预留的字节数, 由 `cargo xtask build` 按实际的表设置, 默认不预留, 见 src/ksyms.rs
+const DEFAULT_KSYMS_SIZE: usize = 0;
+// 编译期日志级别, 可以通过环境变量 GLENDA_LOG_LEVEL 覆盖, 默认 debug 构建为 debug, release 为 info
+const LOG_LEVELS: [&str; 4] = ["error", "warn", "info", "debug"];
+
 fn main() {
     println!("cargo:rerun-if-changed=src/boot.S");
+    println!("cargo:rerun-if-changed=src/trap/entry.S");
     println!("cargo:rerun-if-changed=src/linker.ld");
-    cc::Build::new().file("src/boot.S").flag("-march=rv64gc").flag("-mabi=lp64d").compile("boot");
+    println!("cargo:rerun-if-env-changed=GLENDA_MAX_HARTS");
+    println!("cargo:rerun-if-env-changed=GLENDA_LOG_LEVEL");
+    println!("cargo:rerun-if-env-changed=GLENDA_KSYMS_SIZE");
+
+    let max_harts = match env::var("GLENDA_MAX_HARTS") {
+        Ok(value) => value.parse().expect("GLENDA_MAX_HARTS must be a decimal number"),
+        Err(_) => DEFAULT_MAX_HARTS,
+    };
+    assert!(
+        (1..=MAX_HARTS_LIMIT).contains(&max_harts),
+        "GLENDA_MAX_HARTS must be between 1 and {MAX_HARTS_LIMIT}"
+    );
+    println!("cargo:rustc-env=GLENDA_MAX_HARTS={max_harts}");
+
+    let ksyms_size: usize = match env::var("GLENDA_KSYMS_SIZE") {
+        Ok(value) => value.parse().expect("GLENDA_KSYMS_SIZE must be a decimal number"),
+        Err(_) => DEFAULT_KSYMS_SIZE,
+    };
+    println!("cargo:rustc-env=GLENDA_KSYMS_SIZE={ksyms_size}");
+
+    let log_level = match env::var("GLENDA_LOG_LEVEL") {
+        Ok(value) => value.to_ascii_lowercase(),
+        Err(_) if env::var("PROFILE").as_deref() == Ok("release") => "info".into(),
+        Err(_) => "debug".into(),
+    };
+    assert!(
+        LOG_LEVELS.contains(&log_level.as_str()),
+        "GLENDA_LOG_LEVEL must be one of {LOG_LEVELS:?}"
+    );
+    println!("cargo:rustc-env=GLENDA_LOG_LEVEL={log_level}");
+
+    cc::Build::new()
+        .file("src/boot.S")
+        .file("src/trap/entry.S")
+        .flag("-march=rv64gc")
+        .flag("-mabi=lp64d")
+        .define("CONFIG_MAX_HARTS", max_harts.to_string().as_str())
+        .compile("boot");
 }
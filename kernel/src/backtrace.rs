@@ -0,0 +1,132 @@
+#![allow(dead_code)]
+
+use core::arch::asm;
+use core::fmt;
+use core::sync::atomic::Ordering;
+
+use crate::hart;
+use crate::ksyms::{self, Sym};
+use crate::mm::{self, PAGE_SIZE};
+use crate::percpu;
+
+/*
+ 基于帧指针的栈回溯
+
+ debug 构建由 `cargo xtask build` 打开 -C force-frame-pointers, 每个函数的序言把
+ ra 与上一帧的 fp (s0) 保存在自己栈帧的顶部:
+
+   高地址  +-----------------+ <- fp (进入函数时的 sp)
+           | ra              |  fp - 8
+           | 上一帧的 fp      |  fp - 16
+           | ...             |
+   低地址  +-----------------+ <- sp
+
+ 沿 fp 链向上走, 每一步都检查 fp 对齐且落在本 hart 的内核栈或应急栈之内,
+ 并且在同一个栈内严格向高地址前进, 所以没有帧指针的构建或损坏的栈最多只是得到较短的回溯;
+ 栈溢出后在应急栈上回溯时, fp 链会回到溢出的内核栈
+
+ 输出的是链接地址 (减去 KASLR 偏移), 可以直接交给 `cargo xtask symbolize`;
+ 除标记为 (pc) 的一帧外都是返回地址, 指向调用指令之后.
+ 内核嵌入了符号表时每一帧同时打印 function+offset
+
+ Also see:
+ Glenda/kernel/src/ksyms.rs
+ Glenda/kernel/src/panic.rs
+ Glenda/xtask/src/main.rs
+*/
+const MAX_FRAMES: usize = 32;
+
+pub struct Backtrace {
+    // 陷入时被打断的位置, 不是返回地址
+    pc: Option<usize>,
+    frames: [usize; MAX_FRAMES],
+    len: usize,
+}
+
+impl Backtrace {
+    // 从调用者开始回溯
+    #[inline(never)]
+    pub fn capture() -> Self {
+        let fp: usize;
+        unsafe { asm!("mv {}, s0", out(reg) fp, options(nomem, nostack, preserves_flags)) };
+        Self::walk(None, fp)
+    }
+
+    /*
+     陷入处理中的回溯: 先记录被打断的 pc, 再从当前的 fp 链继续
+     trap/entry.S 不改动 s0, 因此 kernel_trap 的上一帧就是被打断的函数
+    */
+    #[inline(never)]
+    pub fn capture_trap(pc: usize) -> Self {
+        let fp: usize;
+        unsafe { asm!("mv {}, s0", out(reg) fp, options(nomem, nostack, preserves_flags)) };
+        Self::walk(Some(pc), fp)
+    }
+
+    fn walk(pc: Option<usize>, mut fp: usize) -> Self {
+        let mut backtrace = Self { pc, frames: [0; MAX_FRAMES], len: 0 };
+        while backtrace.len < MAX_FRAMES {
+            let Some((bottom, top)) = stack_bounds(fp) else {
+                break;
+            };
+            if !fp.is_multiple_of(size_of::<usize>()) || fp < bottom + 2 * size_of::<usize>() {
+                break;
+            }
+            let (ra, prev) =
+                unsafe { (*(fp as *const usize).sub(1), *(fp as *const usize).sub(2)) };
+            if ra == 0 {
+                break;
+            }
+            backtrace.frames[backtrace.len] = ra;
+            backtrace.len += 1;
+            if (bottom..=top).contains(&prev) && prev <= fp {
+                break;
+            }
+            fp = prev;
+        }
+        backtrace
+    }
+
+    pub fn frames(&self) -> &[usize] {
+        &self.frames[..self.len]
+    }
+}
+
+// 链接地址与运行地址之差由 KASLR 决定
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // 最后一行不带换行, 由 printk! 补上
+        write!(f, "Call trace (KASLR offset 0x{:x}):", mm::kaslr_offset())?;
+        let symbols = ksyms::available();
+        let mut index = 0;
+        if let Some(pc) = self.pc {
+            write!(f, "\n  #{:<2} [<{:016x}>]", index, mm::link_address(pc))?;
+            if symbols {
+                write!(f, " {}", Sym::pc(pc))?;
+            }
+            write!(f, " (pc)")?;
+            index += 1;
+        }
+        for &ra in self.frames() {
+            write!(f, "\n  #{:<2} [<{:016x}>]", index, mm::link_address(ra))?;
+            if symbols {
+                write!(f, " {}", Sym::ret(ra))?;
+            }
+            index += 1;
+        }
+        if index == 0 {
+            write!(f, "\n  (no frames, built without frame pointers?)")?;
+        }
+        Ok(())
+    }
+}
+
+// fp 所在的栈: 本 hart 的内核栈, 或者栈溢出后切换到的应急栈
+fn stack_bounds(fp: usize) -> Option<(usize, usize)> {
+    let (bottom, top) = mm::stack::kernel_stack(hart::current_id());
+    if (bottom..=top).contains(&fp) {
+        return Some((bottom, top));
+    }
+    let top = percpu!(emergency_stack_top).load(Ordering::Acquire);
+    (top != 0 && (top - PAGE_SIZE..=top).contains(&fp)).then_some((top - PAGE_SIZE, top))
+}
@@ -1,106 +1,119 @@
-use core::cell::UnsafeCell;
 use core::cmp;
-use core::hint::spin_loop;
-use core::sync::atomic::{AtomicU8, Ordering};
 
 use driver_uart::Config as UartConfig;
 use fdt::Fdt;
+use sync::OnceCell;
 
-#[derive(Debug, Clone, Copy)]
-pub struct DeviceTreeInfo {
-    uart: Option<UartConfig>,
-    hart_count: usize,
+use crate::hart::MAX_HARTS;
+
+// 物理内存区间 [start, start + size)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Region {
+    pub start: usize,
+    pub size: usize,
 }
 
-impl DeviceTreeInfo {
-    fn new(fdt: &Fdt) -> Self {
-        let hart_count = parse_hart_count(fdt);
-        let uart = parse_uart(fdt);
+impl Region {
+    pub const fn end(&self) -> usize {
+        self.start + self.size
+    }
+}
 
-        Self { uart, hart_count }
+pub const MAX_REGIONS: usize = 16;
+
+// 固定容量的区间表, 超出容量的部分被丢弃
+#[derive(Debug, Clone, Copy)]
+struct Regions {
+    regions: [Region; MAX_REGIONS],
+    len: usize,
+}
+
+impl Regions {
+    const fn new() -> Self {
+        Self { regions: [Region { start: 0, size: 0 }; MAX_REGIONS], len: 0 }
     }
 
-    fn uart(&self) -> Option<UartConfig> {
-        self.uart
+    fn push(&mut self, region: Region) {
+        if self.len < MAX_REGIONS && region.size != 0 {
+            self.regions[self.len] = region;
+            self.len += 1;
+        }
     }
 
-    fn hart_count(&self) -> usize {
-        cmp::max(self.hart_count, 1)
+    fn as_slice(&self) -> &[Region] {
+        &self.regions[..self.len]
     }
 }
 
-const UNINITIALIZED: u8 = 0;
-const INITIALIZING: u8 = 1;
-const READY: u8 = 2;
-
-struct DeviceTreeCell {
-    state: AtomicU8,
-    value: UnsafeCell<Option<DeviceTreeInfo>>,
+// 固定容量的 hart id 表, 超出容量的只计数
+#[derive(Debug, Clone, Copy)]
+struct HartIds {
+    ids: [usize; MAX_HARTS],
+    len: usize,
+    dropped: usize,
 }
 
-impl DeviceTreeCell {
+impl HartIds {
     const fn new() -> Self {
-        Self { state: AtomicU8::new(UNINITIALIZED), value: UnsafeCell::new(None) }
+        Self { ids: [0; MAX_HARTS], len: 0, dropped: 0 }
     }
 
-    fn get(&self) -> Option<&DeviceTreeInfo> {
-        if self.state.load(Ordering::Acquire) == READY {
-            unsafe { (*self.value.get()).as_ref() }
+    fn push(&mut self, hartid: usize) {
+        if self.len < MAX_HARTS {
+            self.ids[self.len] = hartid;
+            self.len += 1;
         } else {
-            None
+            self.dropped += 1;
         }
     }
 
-    fn get_or_try_init<F>(&self, init: F) -> Result<&DeviceTreeInfo, fdt::FdtError>
-    where
-        F: FnOnce() -> Result<DeviceTreeInfo, fdt::FdtError>,
-    {
-        loop {
-            match self.state.load(Ordering::Acquire) {
-                READY => return Ok(self.get_ready()),
-                UNINITIALIZED => {
-                    if self
-                        .state
-                        .compare_exchange(
-                            UNINITIALIZED,
-                            INITIALIZING,
-                            Ordering::AcqRel,
-                            Ordering::Acquire,
-                        )
-                        .is_ok()
-                    {
-                        break;
-                    }
-                }
-                _ => {
-                    while self.state.load(Ordering::Acquire) == INITIALIZING {
-                        spin_loop();
-                    }
-                }
-            }
-        }
+    fn as_slice(&self) -> &[usize] {
+        &self.ids[..self.len]
+    }
+}
 
-        match init() {
-            Ok(info) => unsafe {
-                *self.value.get() = Some(info);
-                self.state.store(READY, Ordering::Release);
-                Ok(self.get_ready())
-            },
-            Err(err) => {
-                self.state.store(UNINITIALIZED, Ordering::Release);
-                Err(err)
-            }
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceTreeInfo {
+    uart: Option<UartConfig>,
+    // 启用的 hart, 下标即逻辑 CPU 编号
+    harts: HartIds,
+    // 启用但 hart id 不小于 MAX_HARTS 的 hart, 内核不使用
+    ignored_harts: HartIds,
+    memory: Regions,
+    reserved: Regions,
+    timebase_frequency: Option<usize>,
+    blob_size: usize,
+}
+
+impl DeviceTreeInfo {
+    fn new(fdt: &Fdt) -> Self {
+        let (harts, ignored_harts) = parse_harts(fdt);
+        let uart = parse_uart(fdt);
+        let memory = parse_memory(fdt);
+        let reserved = parse_reserved(fdt);
+        let timebase_frequency = parse_timebase(fdt);
+
+        Self {
+            uart,
+            harts,
+            ignored_harts,
+            memory,
+            reserved,
+            timebase_frequency,
+            blob_size: fdt.total_size(),
         }
     }
 
-    fn get_ready(&self) -> &DeviceTreeInfo {
-        unsafe { (*self.value.get()).as_ref().unwrap() }
+    fn uart(&self) -> Option<UartConfig> {
+        self.uart
+    }
+
+    fn hart_count(&self) -> usize {
+        cmp::max(self.harts.len, 1)
     }
 }
 
-unsafe impl Sync for DeviceTreeCell {}
-
-static DEVICE_TREE: DeviceTreeCell = DeviceTreeCell::new();
+static DEVICE_TREE: OnceCell<DeviceTreeInfo> = OnceCell::new();
 
 pub fn init(dtb: *const u8) -> Result<&'static DeviceTreeInfo, fdt::FdtError> {
     DEVICE_TREE
@@ -111,10 +124,47 @@ pub fn hart_count() -> usize {
     DEVICE_TREE.get().map(DeviceTreeInfo::hart_count).unwrap_or(1)
 }
 
+// 启用的 hart 的 hart id, 按设备树中的顺序排列, 设备树解析失败时为空
+pub fn hart_ids() -> &'static [usize] {
+    DEVICE_TREE.get().map(|info| info.harts.as_slice()).unwrap_or(&[])
+}
+
+// 被忽略的 hart 的 hart id, 至多记录 MAX_HARTS 个
+pub fn ignored_hart_ids() -> &'static [usize] {
+    DEVICE_TREE.get().map(|info| info.ignored_harts.as_slice()).unwrap_or(&[])
+}
+
+// 被忽略的 hart 总数
+pub fn ignored_hart_count() -> usize {
+    DEVICE_TREE
+        .get()
+        .map(|info| info.harts.dropped + info.ignored_harts.len + info.ignored_harts.dropped)
+        .unwrap_or(0)
+}
+
 pub fn uart_config() -> Option<UartConfig> {
     DEVICE_TREE.get().and_then(DeviceTreeInfo::uart)
 }
 
+// 可用物理内存, 设备树解析失败时为空
+pub fn memory_regions() -> &'static [Region] {
+    DEVICE_TREE.get().map(|info| info.memory.as_slice()).unwrap_or(&[])
+}
+
+// /memreserve/ 与 /reserved-memory 描述的保留区域, 例如 OpenSBI 所在的内存
+pub fn reserved_regions() -> &'static [Region] {
+    DEVICE_TREE.get().map(|info| info.reserved.as_slice()).unwrap_or(&[])
+}
+
+// time CSR 的频率 (Hz)
+pub fn timebase_frequency() -> Option<usize> {
+    DEVICE_TREE.get().and_then(|info| info.timebase_frequency)
+}
+
+pub fn blob_size() -> usize {
+    DEVICE_TREE.get().map(|info| info.blob_size).unwrap_or(0)
+}
+
 fn parse_uart(fdt: &Fdt) -> Option<UartConfig> {
     let chosen = fdt.find_node("/chosen")?;
     let stdout_path = chosen.property("stdout-path")?.as_str()?;
@@ -124,19 +174,68 @@ fn parse_uart(fdt: &Fdt) -> Option<UartConfig> {
     UartConfig::from_fdt(&node)
 }
 
-fn parse_hart_count(fdt: &Fdt) -> usize {
-    let mut count = 0;
+fn parse_memory(fdt: &Fdt) -> Regions {
+    let mut memory = Regions::new();
+    for node in fdt.find_all_nodes("/memory") {
+        for region in node.reg().into_iter().flatten() {
+            memory.push(Region {
+                start: region.starting_address as usize,
+                size: region.size.unwrap_or(0),
+            });
+        }
+    }
+    memory
+}
+
+fn parse_reserved(fdt: &Fdt) -> Regions {
+    let mut reserved = Regions::new();
+    for rsv in fdt.memory_reservations() {
+        reserved.push(Region { start: rsv.address() as usize, size: rsv.size() });
+    }
+    if let Some(node) = fdt.find_node("/reserved-memory") {
+        for child in node.children() {
+            for region in child.reg().into_iter().flatten() {
+                reserved.push(Region {
+                    start: region.starting_address as usize,
+                    size: region.size.unwrap_or(0),
+                });
+            }
+        }
+    }
+    reserved
+}
+
+// 规范要求写在 /cpus 或每个 cpu 节点上, 这里不用 Cpu::timebase_frequency, 它在缺失时会 panic
+fn parse_timebase(fdt: &Fdt) -> Option<usize> {
+    let from_cpus = fdt.find_node("/cpus").and_then(|cpus| cpus.property("timebase-frequency"));
+    let from_cpu = || fdt.cpus().find_map(|cpu| cpu.property("timebase-frequency"));
+    from_cpus.or_else(from_cpu).and_then(|prop| prop.as_usize())
+}
+
+/*
+ hart id 取自每个 cpu 节点的 reg 属性, 可能不连续
+ 跳过 status 不是 "okay" 的节点 (例如 SiFive U74 上没有 S 模式的监控核)
+ per-CPU 数据、内核栈和 IPI 都以 hart id 为下标, hart id 不小于 MAX_HARTS 的 hart 被忽略
+
+ See SPEC: https://devicetree-specification.readthedocs.io/en/stable/devicenodes.html#cpus-cpu-node-properties
+*/
+fn parse_harts(fdt: &Fdt) -> (HartIds, HartIds) {
+    let mut harts = HartIds::new();
+    let mut ignored = HartIds::new();
     for cpu in fdt.cpus() {
-        let disabled = cpu
+        let enabled = cpu
             .property("status")
             .and_then(|prop| prop.as_str())
-            .map(|status| status == "disabled")
-            .unwrap_or(false);
-
-        if !disabled {
-            count += 1;
+            .map(|status| status == "okay" || status == "ok")
+            .unwrap_or(true);
+        if !enabled {
+            continue;
+        }
+        match cpu.property("reg").and_then(|prop| prop.as_usize()) {
+            Some(hartid) if hartid < MAX_HARTS => harts.push(hartid),
+            Some(hartid) => ignored.push(hartid),
+            None => {}
         }
     }
-
-    cmp::max(count, 1)
+    (harts, ignored)
 }
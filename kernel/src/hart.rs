@@ -0,0 +1,68 @@
+use crate::dtb;
+use crate::percpu;
+
+/*
+ hart 相关的基础设施
+
+ 当前 hart 的 hart id 记录在 tp 指向的 per-CPU 数据块中, 见 kernel/src/percpu.rs
+
+ hart id 由固件分配, 可能不连续; 内核内部使用连续的逻辑 CPU 编号,
+ 两者的对应关系按设备树中 cpu 节点的顺序建立
+
+ Also see:
+ Glenda/kernel/src/boot.S
+*/
+
+// hart id 上限 (不含), 由构建时的 GLENDA_MAX_HARTS 决定, 见 kernel/build.rs
+pub const MAX_HARTS: usize = parse_decimal(env!("GLENDA_MAX_HARTS"));
+
+pub const fn parse_decimal(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut value = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    value
+}
+
+unsafe extern "C" {
+    static boot_hartid: usize;
+}
+
+// 在 boot.S 的抽签中胜出、负责全局初始化的 hart
+pub fn boot_id() -> usize {
+    unsafe { boot_hartid }
+}
+
+pub fn is_boot() -> bool {
+    current_id() == boot_id()
+}
+
+pub fn current_id() -> usize {
+    percpu!(hartid).get()
+}
+
+// 逻辑 CPU 数量
+pub fn cpu_count() -> usize {
+    dtb::hart_count()
+}
+
+// hart id -> 逻辑 CPU 编号, 设备树不可用时只有引导 hart (CPU 0)
+pub fn cpu_id(hartid: usize) -> Option<usize> {
+    let ids = dtb::hart_ids();
+    if ids.is_empty() {
+        return (hartid == boot_id()).then_some(0);
+    }
+    ids.iter().position(|&id| id == hartid)
+}
+
+// 逻辑 CPU 编号 -> hart id
+pub fn hart_id(cpu: usize) -> Option<usize> {
+    let ids = dtb::hart_ids();
+    if ids.is_empty() {
+        return (cpu == 0).then(boot_id);
+    }
+    ids.get(cpu).copied()
+}
@@ -1,8 +1,11 @@
 use core::arch::asm;
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use crate::printk;
-use crate::printk::{ANSI_BLUE, ANSI_RED, ANSI_RESET};
+use crate::dtb;
+use crate::hart;
+use crate::mm;
+use crate::mm::stack::StackError;
+use crate::{pr_err, pr_info};
 
 /*
  Hart State Management[1]
@@ -11,6 +14,7 @@ use crate::printk::{ANSI_BLUE, ANSI_RED, ANSI_RESET};
 */
 const SBI_EXT_HSM: usize = 0x48534d;
 const SBI_FUNC_HART_START: usize = 0;
+const SBI_ERR_ALREADY_AVAILABLE: isize = -6;
 
 static BOOTSTRAP_DONE: AtomicBool = AtomicBool::new(false);
 /*
@@ -42,28 +46,44 @@ unsafe fn sbi_hart_start(hartid: usize, start_addr: usize, opaque: usize) -> Res
     if err == 0 { Ok(()) } else { Err(err) }
 }
 
-// 由第一个进来的 hart 调用一次，启动其余参与测试的次级 hart
+/*
+ 由引导 hart 调用一次，启动其余次级 hart
+
+ 在 boot.S 抽签中落选的 hart 已经在运行, 映射好内核栈后它们会自行继续,
+ 此时 HSM 返回 SBI_ERR_ALREADY_AVAILABLE
+*/
 pub fn bootstrap_secondary_harts(hartid: usize, dtb: *const u8) {
-    if BOOTSTRAP_DONE.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+    if !hart::is_boot()
+        || BOOTSTRAP_DONE.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err()
+    {
         return;
     }
+    // 没有栈槽的 hart 不启动, 它们被 SBI 启动的话只会在 boot.S 中停机
+    for &target in dtb::ignored_hart_ids() {
+        pr_err!("not starting hart {}: {}", target, StackError::NoSlot(target));
+    }
     unsafe {
-        let start_addr = secondary_start as usize;
+        // 次级 hart 在分页关闭时进入, 需要传入物理地址
+        let start_addr = mm::kernel_virt_to_phys(secondary_start as *const () as usize);
         let opaque = dtb as usize;
-        let harts = crate::dtb::hart_count();
-        for target in 0..harts {
+        // 只启动设备树中存在且启用的 hart
+        for cpu in 0..hart::cpu_count() {
+            let Some(target) = hart::hart_id(cpu) else {
+                continue;
+            };
             if target == hartid {
                 continue;
             }
+            if let Err(err) = mm::stack::prepare(target) {
+                pr_err!("cannot start hart {}: {}", target, err);
+                continue;
+            }
             match sbi_hart_start(target, start_addr, opaque) {
-                Ok(()) => printk!("{}Started hart {} via SBI{}", ANSI_BLUE, target, ANSI_RESET),
-                Err(err) => printk!(
-                    "{}Failed to start hart {} via SBI: error {}{}",
-                    ANSI_RED,
-                    target,
-                    err,
-                    ANSI_RESET
-                ),
+                Ok(()) => pr_info!("started hart {} (cpu {}) via SBI", target, cpu),
+                Err(SBI_ERR_ALREADY_AVAILABLE) => {
+                    pr_info!("released waiting hart {} (cpu {})", target, cpu)
+                }
+                Err(err) => pr_err!("failed to start hart {} via SBI: error {}", target, err),
             }
         }
     }
@@ -1,5 +1,42 @@
 mod harts;
 
+use crate::hart;
+use crate::ipi;
+use crate::mm;
+use crate::percpu;
+use crate::pr_err;
+use crate::rcu;
+use crate::timer;
+use crate::trace;
+
+pub fn init_percpu(hartid: usize) {
+    percpu::init(hart::cpu_id(hartid));
+}
+
+pub fn init_mm(hartid: usize, dtb: *const u8) {
+    mm::init(dtb as usize);
+    // 引导 hart 的应急栈, 其余 hart 在启动前已经准备好
+    if let Err(err) = mm::stack::prepare(hartid) {
+        pr_err!("hart {}: no emergency stack: {}", hartid, err);
+    }
+}
+
 pub fn init_harts(hartid: usize, dtb: *const u8) {
     harts::bootstrap_secondary_harts(hartid, dtb);
 }
+
+pub fn init_rcu() {
+    rcu::online();
+}
+
+pub fn init_trace() {
+    trace::init();
+}
+
+pub fn init_ipi() {
+    ipi::init();
+}
+
+pub fn init_timer() {
+    timer::init();
+}
@@ -0,0 +1,97 @@
+#![allow(dead_code)]
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use riscv::register::{sie, sip};
+
+use crate::hart::{self, MAX_HARTS};
+use crate::panic;
+use crate::trace;
+use crate::trap::TrapFrame;
+
+/*
+ 核间中断 (IPI)
+
+ 通过 SBI IPI 扩展[1] 向其它 hart 发送 supervisor 软件中断,
+ 发送前先在目标 hart 的 pending 中置上原因位, 目标在陷入处理中清除 sip.SSIP 后逐个处理
+
+ [1]: https://www.scs.stanford.edu/~zyedidia/docs/riscv/riscv-sbi.pdf, Chapter Seven
+
+ Also see:
+ Glenda/kernel/src/trap/mod.rs
+ Glenda/kernel/src/panic.rs
+*/
+const SBI_EXT_IPI: usize = 0x735049;
+const SBI_FUNC_SEND_IPI: usize = 0;
+
+// 原因位
+pub const IPI_STOP: usize = 1 << 0;
+
+struct HartIpi {
+    // 已开启软件中断, 可以接收 IPI
+    online: AtomicBool,
+    pending: AtomicUsize,
+}
+
+impl HartIpi {
+    const fn new() -> Self {
+        Self { online: AtomicBool::new(false), pending: AtomicUsize::new(0) }
+    }
+}
+
+static IPI: [HartIpi; MAX_HARTS] = [const { HartIpi::new() }; MAX_HARTS];
+
+#[inline(always)]
+fn sbi_send_ipi(hart_mask: usize, hart_mask_base: usize) -> Result<(), isize> {
+    let error: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") hart_mask => error,
+            inlateout("a1") hart_mask_base => _,
+            in("a6") SBI_FUNC_SEND_IPI,
+            in("a7") SBI_EXT_IPI,
+            options(nostack)
+        );
+    }
+    if error == 0 { Ok(()) } else { Err(error) }
+}
+
+// 在每个 hart 上调用一次, 之后本 hart 可以接收 IPI
+pub fn init() {
+    unsafe { sie::set_ssoft() };
+    IPI[hart::current_id()].online.store(true, Ordering::Release);
+}
+
+pub fn is_online(hartid: usize) -> bool {
+    IPI.get(hartid).is_some_and(|ipi| ipi.online.load(Ordering::Acquire))
+}
+
+// 目标不能接收 IPI 时返回 false
+pub fn send(hartid: usize, reason: usize) -> bool {
+    if !is_online(hartid) {
+        return false;
+    }
+    IPI[hartid].pending.fetch_or(reason, Ordering::AcqRel);
+    trace!(IpiSend, hartid, reason);
+    sbi_send_ipi(1, hartid).is_ok()
+}
+
+// 向除自己以外所有可以接收 IPI 的 hart 发送, 返回发出的数量
+pub fn send_others(reason: usize) -> usize {
+    let me = hart::current_id();
+    (0..hart::cpu_count())
+        .filter_map(hart::hart_id)
+        .filter(|&hartid| hartid != me && send(hartid, reason))
+        .count()
+}
+
+// 由 kernel_trap 在软件中断中调用
+pub fn handle(frame: &TrapFrame) {
+    unsafe { sip::clear_ssoft() };
+    let pending = IPI[hart::current_id()].pending.swap(0, Ordering::AcqRel);
+    if pending & IPI_STOP != 0 {
+        panic::stop_this_hart(frame);
+    }
+}
@@ -0,0 +1,149 @@
+#![allow(dead_code)]
+
+use core::fmt;
+use core::slice;
+
+use crate::hart;
+use crate::mm::{self, KERNEL_LINK_BASE};
+
+/*
+ 内核符号表 (kallsyms)
+
+ 链接时在 .ksyms 段预留 KSYMS_SIZE 字节的全零空间, `cargo xtask build` 链接完成后
+ 从 ELF 的符号表生成按地址排序的函数表并原地写入该段.
+ 预留的大小由构建时的 GLENDA_KSYMS_SIZE 决定: xtask 先链接一次算出表的大小,
+ 与预留的不符时按实际大小重新链接; .ksyms 位于 .text 与 .rodata 之后, 调整大小不会移动任何函数.
+ 直接用 cargo build 得到的内核不预留空间, 回溯只打印地址
+
+ 格式 (小端):
+   0            magic "KSYM"
+   4            count: u32
+   8            count 个表项 { addr: u32, size: u32, name: u32 }
+                addr 为相对 KERNEL_LINK_BASE 的偏移, name 为名字在字符串区中的偏移
+   8 + 12*count 字符串区, 每个名字为 len: u16 加 len 字节的 UTF-8 (去掉了 hash 的 Rust 名字)
+
+ Also see:
+ Glenda/kernel/src/linker.ld
+ Glenda/kernel/src/backtrace.rs
+ Glenda/xtask/src/ksyms.rs
+*/
+pub const KSYMS_SIZE: usize = hart::parse_decimal(env!("GLENDA_KSYMS_SIZE"));
+
+const MAGIC: &[u8; 4] = b"KSYM";
+const HEADER_SIZE: usize = 8;
+const ENTRY_SIZE: usize = 12;
+
+#[used]
+#[unsafe(link_section = ".ksyms")]
+static KSYMS_SPACE: [u8; KSYMS_SIZE] = [0; KSYMS_SIZE];
+
+unsafe extern "C" {
+    static __ksyms_start: u8;
+    static __ksyms_end: u8;
+}
+
+// 经由链接器符号读取, 避免编译器把全零的初值当作常量折叠掉
+fn blob() -> &'static [u8] {
+    let start = &raw const __ksyms_start;
+    let end = &raw const __ksyms_end;
+    unsafe { slice::from_raw_parts(start, end as usize - start as usize) }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<usize> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+struct Table {
+    count: usize,
+    entries: &'static [u8],
+    names: &'static [u8],
+}
+
+fn table() -> Option<Table> {
+    let blob = blob();
+    if blob.get(..MAGIC.len())? != MAGIC {
+        return None;
+    }
+    let count = read_u32(blob, MAGIC.len())?;
+    let names = HEADER_SIZE.checked_add(count.checked_mul(ENTRY_SIZE)?)?;
+    Some(Table { count, entries: blob.get(HEADER_SIZE..names)?, names: blob.get(names..)? })
+}
+
+impl Table {
+    // (addr, size, name)
+    fn entry(&self, index: usize) -> Option<(usize, usize, usize)> {
+        let offset = index * ENTRY_SIZE;
+        Some((
+            read_u32(self.entries, offset)?,
+            read_u32(self.entries, offset + 4)?,
+            read_u32(self.entries, offset + 8)?,
+        ))
+    }
+
+    fn name(&self, offset: usize) -> Option<&'static str> {
+        let len = self.names.get(offset..offset + 2)?;
+        let len = u16::from_le_bytes(len.try_into().unwrap()) as usize;
+        core::str::from_utf8(self.names.get(offset + 2..offset + 2 + len)?).ok()
+    }
+}
+
+// 是否嵌入了符号表
+pub fn available() -> bool {
+    table().is_some()
+}
+
+pub struct Symbol {
+    pub name: &'static str,
+    // addr 相对函数起始的偏移
+    pub offset: usize,
+    pub size: usize,
+}
+
+// 查找内核镜像内运行地址 va 所在的函数
+pub fn lookup(va: usize) -> Option<Symbol> {
+    let table = table()?;
+    let target = mm::link_address(va).checked_sub(KERNEL_LINK_BASE)?;
+    // 最后一个起始地址不大于 target 的表项
+    let (mut low, mut high) = (0, table.count);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if table.entry(mid)?.0 <= target {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    let (addr, size, name) = table.entry(low.checked_sub(1)?)?;
+    let offset = target - addr;
+    (offset < size).then_some(Symbol { name: table.name(name)?, offset, size })
+}
+
+/*
+ 按 name+0xoffset/0xsize 打印, 找不到时打印 "?"
+ 返回地址指向调用指令之后, 调用若是函数的最后一条指令就会落到下一个函数, 因此按 addr - 1 查找
+*/
+pub struct Sym {
+    addr: usize,
+    ret: bool,
+}
+
+impl Sym {
+    pub fn pc(addr: usize) -> Self {
+        Self { addr, ret: false }
+    }
+
+    pub fn ret(addr: usize) -> Self {
+        Self { addr, ret: true }
+    }
+}
+
+impl fmt::Display for Sym {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let adjust = self.ret as usize;
+        match lookup(self.addr.wrapping_sub(adjust)) {
+            Some(sym) => write!(f, "{}+0x{:x}/0x{:x}", sym.name, sym.offset + adjust, sym.size),
+            None => write!(f, "?"),
+        }
+    }
+}
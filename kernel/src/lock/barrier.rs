@@ -0,0 +1,91 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::SpinWait;
+
+/*
+ 可重复使用的屏障 (sense-reversing barrier)
+
+ parties 个 hart 都调用 wait() 之后才一起返回; 最后到达者把计数清零并翻转 sense,
+ 其余 hart 等待 sense 变成本轮的值. 每轮的 sense 都与上一轮相反,
+ 所以同一个屏障可以连续使用, 跑得快的 hart 进入下一轮也不会把上一轮的等待者放走
+
+ 进入 wait() 时读到的 sense 一定是上一轮的值: 本轮翻转需要所有参与者到达, 包括自己
+
+ 参与者数量通常在启动后才知道, 可以配合 sync::Lazy 使用:
+
+   static BARRIER: Lazy<Barrier> = Lazy::new(|| Barrier::new(hart::cpu_count()));
+
+ Also see:
+ Glenda/kernel/src/tests/barrier.rs
+*/
+pub struct Barrier {
+    parties: usize,
+    count: AtomicUsize,
+    sense: AtomicBool,
+}
+
+impl Barrier {
+    pub const fn new(parties: usize) -> Self {
+        Self { parties, count: AtomicUsize::new(0), sense: AtomicBool::new(false) }
+    }
+
+    pub fn parties(&self) -> usize {
+        self.parties
+    }
+
+    // 最后到达的 hart 返回 true
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn wait(&self) -> bool {
+        self.arrive(|| ()).is_some()
+    }
+
+    // 最后到达者先执行 last 再放行其余 hart, 因此 last 的副作用对所有参与者可见
+    #[cfg_attr(debug_assertions, track_caller)]
+    fn arrive<R>(&self, last: impl FnOnce() -> R) -> Option<R> {
+        let sense = !self.sense.load(Ordering::Relaxed);
+        if self.count.fetch_add(1, Ordering::AcqRel) + 1 == self.parties {
+            let result = last();
+            // 下一轮的到达者必须先看到新的 sense, 所以清零在翻转之前
+            self.count.store(0, Ordering::Relaxed);
+            self.sense.store(sense, Ordering::Release);
+            return Some(result);
+        }
+        let mut wait = SpinWait::new(self);
+        while self.sense.load(Ordering::Acquire) != sense {
+            wait.spin();
+        }
+        None
+    }
+}
+
+/*
+ 集合点: 所有参与者到齐后由最后到达者执行一段代码, 执行完毕后一起继续
+
+   ROUND.run(|| reset_stats());   // 只执行一次, 返回时所有 hart 都能看到结果
+
+ 与 Barrier 一样可以重复使用
+*/
+pub struct Rendezvous {
+    barrier: Barrier,
+}
+
+impl Rendezvous {
+    pub const fn new(parties: usize) -> Self {
+        Self { barrier: Barrier::new(parties) }
+    }
+
+    pub fn parties(&self) -> usize {
+        self.barrier.parties()
+    }
+
+    // 最后到达者返回 Some(last 的结果), 其余返回 None
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn run<R>(&self, last: impl FnOnce() -> R) -> Option<R> {
+        self.barrier.arrive(last)
+    }
+
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn wait(&self) -> bool {
+        self.barrier.wait()
+    }
+}
@@ -0,0 +1,408 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::{RawLock, RawSpinLock, SpinWait};
+use crate::hart::{self, MAX_HARTS};
+use crate::percpu;
+use crate::pr_err;
+use crate::trap;
+
+/*
+ 锁依赖检查 (lockdep), 只在 debug 构建中启用
+
+ 锁类 (class) 以 Lock::new 的调用位置区分, 同一处创建的锁 (例如每个 hart 的 magazine)
+ 属于同一类; 同一处创建、却可能相互嵌套的锁 (例如各个 SlabCache 的 depot) 用
+ Lock::new_with_class 再加上名字区分
+ 每次加锁时把 "已持有的类 -> 新类" 记为一条依赖边:
+
+   - 新边与已有的边构成环: 存在 ABBA 死锁的可能
+   - 同一类既在陷入处理中获取, 又在开中断的情况下获取: 陷入可能打断持有者, 在同一 hart 上死锁
+
+ 问题在第一次观察到不一致时报告, 同时给出两处加锁位置; 依赖边仍然会被记录,
+ 因此同一问题只报告一次
+ try_lock 不会等待, 不检查顺序, 但成功后计入已持有的锁
+
+ 检查本身使用不受检查的 RawSpinLock, 并在关中断的情况下进行
+
+ Also see:
+ Glenda/kernel/src/lock/mod.rs
+ Glenda/kernel/src/tests/lockdep.rs
+*/
+pub type Site = &'static Location<'static>;
+
+// 锁类: 创建锁的位置, 以及可选的名字
+#[derive(Clone, Copy)]
+pub struct Key {
+    name: Option<&'static str>,
+    site: Site,
+}
+
+impl Key {
+    pub const fn new(name: Option<&'static str>, site: Site) -> Self {
+        Self { name, site }
+    }
+
+    fn same(self, other: Key) -> bool {
+        self.name == other.name && same_site(self.site, other.site)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{} ({})", name, self.site),
+            None => write!(f, "{}", self.site),
+        }
+    }
+}
+
+const MAX_CLASSES: usize = 256;
+const MAX_EDGES: usize = 1024;
+// 每个 hart 同时持有的锁数
+const MAX_HELD: usize = 16;
+// 报告中最多列出的依赖链长度
+const MAX_CHAIN: usize = 4;
+const NO_EDGE: u16 = u16::MAX;
+
+const _: () = assert!(MAX_EDGES < NO_EDGE as usize);
+
+#[derive(Clone, Copy)]
+struct Class {
+    key: Key,
+    // 第一次在陷入处理中获取的位置
+    irq_site: Option<Site>,
+    // 第一次在开中断的情况下获取的位置
+    irq_enabled_site: Option<Site>,
+}
+
+#[derive(Clone, Copy)]
+struct Edge {
+    from: usize,
+    to: usize,
+    // 持有 from 时获取 to, 两者各自的加锁位置
+    from_site: Site,
+    to_site: Site,
+}
+
+struct Graph {
+    classes: [Option<Class>; MAX_CLASSES],
+    class_count: usize,
+    edges: [Option<Edge>; MAX_EDGES],
+    edge_count: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Held {
+    class: usize,
+    key: Key,
+    site: Site,
+}
+
+struct HeldLocks {
+    locks: [Option<Held>; MAX_HELD],
+    len: usize,
+}
+
+// 只由所属 hart 在关中断的情况下访问
+struct PerHart(UnsafeCell<HeldLocks>);
+
+unsafe impl Sync for PerHart {}
+
+struct GraphCell(UnsafeCell<Graph>);
+
+// 只在持有 GRAPH_LOCK 时访问
+unsafe impl Sync for GraphCell {}
+
+static GRAPH_LOCK: RawSpinLock = RawSpinLock::INIT;
+static GRAPH: GraphCell = GraphCell(UnsafeCell::new(Graph {
+    classes: [None; MAX_CLASSES],
+    class_count: 0,
+    edges: [None; MAX_EDGES],
+    edge_count: 0,
+}));
+static HELD: [PerHart; MAX_HARTS] =
+    [const { PerHart(UnsafeCell::new(HeldLocks { locks: [None; MAX_HELD], len: 0 })) }; MAX_HARTS];
+
+// 表满之后停止检查
+static DISABLED: AtomicBool = AtomicBool::new(false);
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+// 报告只在栈上短暂存在, 不能为了缩小 Deadlock 在检查过程中分配内存
+#[allow(clippy::large_enum_variant)]
+enum Report {
+    Recursive { class: Key, held_site: Site, site: Site },
+    Deadlock { class: Key, site: Site, held: Key, held_site: Site, chain: Chain },
+    IrqUnsafe { class: Key, irq_site: Site, irq_enabled_site: Site },
+    Overflow(&'static str),
+}
+
+// 依赖链上的一步: 持有 from 时获取 to
+#[derive(Clone, Copy)]
+struct Link {
+    from: Key,
+    from_site: Site,
+    to: Key,
+    to_site: Site,
+}
+
+// 从新类回到已持有类的依赖链, 只保留前 MAX_CHAIN 步
+struct Chain {
+    links: [Option<Link>; MAX_CHAIN],
+    len: usize,
+}
+
+impl Graph {
+    fn class_of(&mut self, key: Key) -> Option<usize> {
+        let known = &self.classes[..self.class_count];
+        if let Some(index) = known.iter().flatten().position(|class| class.key.same(key)) {
+            return Some(index);
+        }
+        let index = self.class_count;
+        *self.classes.get_mut(index)? = Some(Class { key, irq_site: None, irq_enabled_site: None });
+        self.class_count += 1;
+        Some(index)
+    }
+
+    fn class(&self, index: usize) -> &Class {
+        self.classes[index].as_ref().unwrap()
+    }
+
+    fn has_edge(&self, from: usize, to: usize) -> bool {
+        self.edges[..self.edge_count]
+            .iter()
+            .flatten()
+            .any(|edge| edge.from == from && edge.to == to)
+    }
+
+    fn add_edge(&mut self, edge: Edge) -> bool {
+        let Some(slot) = self.edges.get_mut(self.edge_count) else {
+            return false;
+        };
+        *slot = Some(edge);
+        self.edge_count += 1;
+        true
+    }
+
+    fn edge(&self, index: u16) -> &Edge {
+        self.edges[index as usize].as_ref().unwrap()
+    }
+
+    /*
+     深度优先搜索 from 到 to 的依赖链
+     运行在内核栈上, 因此只用 u16 下标记录到达每个类所经过的边
+    */
+    fn find_chain(&self, from: usize, to: usize) -> Option<Chain> {
+        let mut via = [NO_EDGE; MAX_CLASSES];
+        let mut visited = [false; MAX_CLASSES];
+        let mut stack = [0u16; MAX_CLASSES];
+        let mut depth = 1;
+        stack[0] = from as u16;
+        visited[from] = true;
+
+        while depth > 0 {
+            depth -= 1;
+            let node = stack[depth] as usize;
+            if node == to {
+                return Some(self.chain(via, from, to));
+            }
+            for (index, edge) in self.edges[..self.edge_count].iter().enumerate() {
+                let edge = edge.as_ref().unwrap();
+                if edge.from == node && !visited[edge.to] {
+                    visited[edge.to] = true;
+                    via[edge.to] = index as u16;
+                    stack[depth] = edge.to as u16;
+                    depth += 1;
+                }
+            }
+        }
+        None
+    }
+
+    // 沿 via 从 to 倒推回 from
+    fn chain(&self, via: [u16; MAX_CLASSES], from: usize, to: usize) -> Chain {
+        let mut steps = 0;
+        let mut at = to;
+        while at != from {
+            steps += 1;
+            at = self.edge(via[at]).from;
+        }
+
+        let mut chain = Chain { links: [None; MAX_CHAIN], len: steps.min(MAX_CHAIN) };
+        let mut at = to;
+        for step in (0..steps).rev() {
+            let edge = self.edge(via[at]);
+            if step < MAX_CHAIN {
+                chain.links[step] = Some(Link {
+                    from: self.class(edge.from).key,
+                    from_site: edge.from_site,
+                    to: self.class(edge.to).key,
+                    to_site: edge.to_site,
+                });
+            }
+            at = edge.from;
+        }
+        chain
+    }
+}
+
+fn same_site(a: Site, b: Site) -> bool {
+    a.line() == b.line() && a.column() == b.column() && a.file() == b.file()
+}
+
+fn held() -> &'static mut HeldLocks {
+    unsafe { &mut *HELD[hart::current_id()].0.get() }
+}
+
+// 在即将等待一把锁之前调用, 因此报告先于可能的死锁打印出来
+pub fn acquire(key: Key, site: Site, trylock: bool) {
+    if DISABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let irq_enabled = trap::local_irq_save();
+    let in_interrupt = percpu::in_interrupt();
+
+    GRAPH_LOCK.acquire(&mut SpinWait::new(&GRAPH_LOCK));
+    let graph = unsafe { &mut *GRAPH.0.get() };
+    let report = check(graph, key, site, trylock, irq_enabled, in_interrupt);
+    unsafe { GRAPH_LOCK.release(()) };
+
+    trap::local_irq_restore(irq_enabled);
+    if let Some(report) = report {
+        print_report(report);
+    }
+}
+
+fn check(
+    graph: &mut Graph,
+    key: Key,
+    site: Site,
+    trylock: bool,
+    irq_enabled: bool,
+    in_interrupt: bool,
+) -> Option<Report> {
+    let Some(class) = graph.class_of(key) else {
+        DISABLED.store(true, Ordering::Relaxed);
+        return Some(Report::Overflow("lock class table full"));
+    };
+    let held = held();
+    let mut report = None;
+
+    // 陷入处理中获取的锁, 在其它地方必须关中断获取
+    let entry = graph.classes[class].as_mut().unwrap();
+    let before = (entry.irq_site.is_some(), entry.irq_enabled_site.is_some());
+    if in_interrupt {
+        entry.irq_site.get_or_insert(site);
+    } else if irq_enabled {
+        entry.irq_enabled_site.get_or_insert(site);
+    }
+    if let (Some(irq_site), Some(irq_enabled_site)) = (entry.irq_site, entry.irq_enabled_site)
+        && before != (true, true)
+    {
+        report = Some(Report::IrqUnsafe { class: key, irq_site, irq_enabled_site });
+    }
+
+    if !trylock {
+        for held_lock in held.locks[..held.len].iter().flatten() {
+            if held_lock.class == class {
+                report.get_or_insert(Report::Recursive {
+                    class: key,
+                    held_site: held_lock.site,
+                    site,
+                });
+                continue;
+            }
+            if graph.has_edge(held_lock.class, class) {
+                continue;
+            }
+            // 已经存在 class -> ... -> held 的依赖, 再加 held -> class 就构成环
+            if let Some(chain) = graph.find_chain(class, held_lock.class) {
+                report.get_or_insert(Report::Deadlock {
+                    class: key,
+                    site,
+                    held: graph.class(held_lock.class).key,
+                    held_site: held_lock.site,
+                    chain,
+                });
+            }
+            let edge =
+                Edge { from: held_lock.class, to: class, from_site: held_lock.site, to_site: site };
+            if !graph.add_edge(edge) {
+                DISABLED.store(true, Ordering::Relaxed);
+                return Some(Report::Overflow("lock dependency table full"));
+            }
+        }
+    }
+
+    if held.len == MAX_HELD {
+        DISABLED.store(true, Ordering::Relaxed);
+        return Some(Report::Overflow("too many locks held"));
+    }
+    held.locks[held.len] = Some(Held { class, key, site });
+    held.len += 1;
+    report
+}
+
+// 守卫释放时调用
+pub fn release(key: Key) {
+    if DISABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let irq_enabled = trap::local_irq_save();
+    let held = held();
+    // 守卫不一定按加锁的逆序释放, 从栈顶开始找
+    let position = held.locks[..held.len]
+        .iter()
+        .rposition(|held_lock| held_lock.is_some_and(|held_lock| held_lock.key.same(key)));
+    if let Some(position) = position {
+        held.locks.copy_within(position + 1..held.len, position);
+        held.len -= 1;
+        held.locks[held.len] = None;
+    }
+    trap::local_irq_restore(irq_enabled);
+}
+
+fn print_report(report: Report) {
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    let hartid = hart::current_id();
+    match report {
+        Report::Recursive { class, held_site, site } => {
+            pr_err!("possible recursive locking on hart {}", hartid);
+            pr_err!("  lock class {} acquired at {}", class, site);
+            pr_err!("  already held, acquired at {}", held_site);
+        }
+        Report::Deadlock { class, site, held, held_site, chain } => {
+            pr_err!("possible ABBA deadlock on hart {}", hartid);
+            pr_err!("  lock class {} acquired at {}", class, site);
+            pr_err!("  while holding lock class {} acquired at {}", held, held_site);
+            pr_err!("  but the reverse order was seen before:");
+            for link in chain.links[..chain.len].iter().flatten() {
+                pr_err!(
+                    "    holding {} (acquired at {}), then took {} at {}",
+                    link.from,
+                    link.from_site,
+                    link.to,
+                    link.to_site
+                );
+            }
+        }
+        Report::IrqUnsafe { class, irq_site, irq_enabled_site } => {
+            pr_err!("irq-unsafe lock on hart {}", hartid);
+            pr_err!("  lock class {} acquired in a trap handler at {}", class, irq_site);
+            pr_err!("  and with interrupts enabled at {}", irq_enabled_site);
+        }
+        Report::Overflow(reason) => {
+            pr_err!("{}, turning off lock checking", reason);
+        }
+    }
+}
+
+// 目前为止报告过的问题数
+pub fn report_count() -> usize {
+    REPORTS.load(Ordering::Relaxed)
+}
+
+pub fn is_enabled() -> bool {
+    !DISABLED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,122 @@
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+use super::{RawLock, SpinWait};
+use crate::hart::{self, MAX_HARTS};
+
+/*
+ MCS 排队锁
+
+ 等待者把自己的节点挂到队尾, 然后只轮询自己节点上的 waiting,
+ 前驱解锁时清除它; 每个等待者自旋在不同的缓存行上, 竞争时不会互相干扰
+
+ 节点不放在守卫里 (守卫会被移动), 而是每个 hart 预留 MCS_NODES 个,
+ 支持陷入处理中再获取其它 MCS 锁; 守卫释放顺序不要求与加锁顺序相反
+*/
+const MCS_NODES: usize = 4;
+
+#[repr(align(64))]
+pub struct McsNode {
+    next: AtomicPtr<McsNode>,
+    waiting: AtomicBool,
+    in_use: AtomicBool,
+}
+
+impl McsNode {
+    const fn new() -> Self {
+        Self {
+            next: AtomicPtr::new(ptr::null_mut()),
+            waiting: AtomicBool::new(false),
+            in_use: AtomicBool::new(false),
+        }
+    }
+}
+
+static NODES: [[McsNode; MCS_NODES]; MAX_HARTS] =
+    [const { [const { McsNode::new() }; MCS_NODES] }; MAX_HARTS];
+
+// 占用本 hart 的一个空闲节点; in_use 只被本 hart 修改, 原子操作防止被陷入打断
+fn claim_node() -> &'static McsNode {
+    let hartid = hart::current_id();
+    NODES[hartid]
+        .iter()
+        .find(|node| !node.in_use.swap(true, Ordering::Relaxed))
+        .unwrap_or_else(|| panic!("hart {}: more than {} MCS locks held", hartid, MCS_NODES))
+}
+
+pub struct RawMcsLock {
+    tail: AtomicPtr<McsNode>,
+}
+
+impl RawLock for RawMcsLock {
+    type Token = *const McsNode;
+
+    const INIT: Self = Self { tail: AtomicPtr::new(ptr::null_mut()) };
+
+    fn acquire(&self, wait: &mut SpinWait) -> *const McsNode {
+        let node = claim_node();
+        node.next.store(ptr::null_mut(), Ordering::Relaxed);
+        node.waiting.store(true, Ordering::Relaxed);
+
+        let node_ptr = node as *const McsNode as *mut McsNode;
+        let prev = self.tail.swap(node_ptr, Ordering::AcqRel);
+        if !prev.is_null() {
+            // 前驱的节点在它把锁交给我们之前一直有效
+            unsafe { (*prev).next.store(node_ptr, Ordering::Release) };
+            while node.waiting.load(Ordering::Acquire) {
+                wait.spin();
+            }
+        }
+        node
+    }
+
+    fn try_acquire(&self) -> Option<*const McsNode> {
+        let node = claim_node();
+        node.next.store(ptr::null_mut(), Ordering::Relaxed);
+        let node_ptr = node as *const McsNode as *mut McsNode;
+        match self.tail.compare_exchange(
+            ptr::null_mut(),
+            node_ptr,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => Some(node),
+            Err(_) => {
+                node.in_use.store(false, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    unsafe fn release(&self, token: *const McsNode) {
+        let node = unsafe { &*token };
+        let mut next = node.next.load(Ordering::Acquire);
+        if next.is_null() {
+            // 没有后继, 直接清空队列
+            let node_ptr = token as *mut McsNode;
+            if self
+                .tail
+                .compare_exchange(node_ptr, ptr::null_mut(), Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                node.in_use.store(false, Ordering::Relaxed);
+                return;
+            }
+            // 后继已经换掉了 tail, 等它把自己挂上来
+            let mut wait = SpinWait::new(self);
+            loop {
+                next = node.next.load(Ordering::Acquire);
+                if !next.is_null() {
+                    break;
+                }
+                wait.spin();
+            }
+        }
+        unsafe { (*next).waiting.store(false, Ordering::Release) };
+        node.in_use.store(false, Ordering::Relaxed);
+    }
+
+    fn is_locked(&self) -> bool {
+        !self.tail.load(Ordering::Relaxed).is_null()
+    }
+}
@@ -0,0 +1,310 @@
+#![allow(dead_code)]
+
+use core::cell::UnsafeCell;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(debug_assertions)]
+use core::panic::Location;
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(debug_assertions)]
+use crate::hart;
+use crate::trap;
+
+pub mod barrier;
+#[cfg(debug_assertions)]
+pub mod lockdep;
+mod mcs;
+pub mod rwlock;
+pub mod seqlock;
+mod spin;
+mod ticket;
+mod wait;
+
+pub use mcs::RawMcsLock;
+pub use spin::RawSpinLock;
+pub use ticket::RawTicketLock;
+pub use wait::SpinWait;
+
+/*
+ 自旋锁
+
+ Lock<R, T> 保护一份数据, lock() 返回的守卫解引用为 T, 离开作用域时自动解锁,
+ 因此提前返回不会把锁遗留在持有状态; R 是具体的加锁算法:
+
+   SpinLock   test-and-test-and-set, 无竞争时最快, 不保证公平
+   TicketLock 按取号顺序获得锁, 公平, 但所有等待者轮询同一个缓存行
+   McsLock    排队锁, 每个等待者轮询自己的节点, 适合高竞争的路径
+
+ 三者的守卫 API 相同, 可以只改类型来切换算法
+ 读多写少的数据另见 rwlock::RwSpinLock 与 seqlock::SeqLock, 多个 hart 之间的同步点见 barrier.rs
+
+ lock_irqsave() 在关闭本 hart 的 sstatus.SIE 之后再加锁, 守卫释放时恢复原来的状态,
+ 用于可能在陷入处理中再次获取的锁 (例如 printk)
+
+ debug 构建中记录持有者的 hart id, 同一 hart 重复加锁时直接 panic 而不是死锁,
+ 等待过久时报告持有者 (见 wait.rs);
+ 同时以 new() 的调用位置作为锁类交给 lockdep 检查加锁顺序, 见 lockdep.rs;
+ 同一处创建的多把锁可能相互嵌套时, 用 new_with_class() 额外给出锁类的名字
+
+ Also see:
+ Glenda/kernel/src/tests/spinlock.rs
+*/
+pub type SpinLock<T> = Lock<RawSpinLock, T>;
+pub type TicketLock<T> = Lock<RawTicketLock, T>;
+pub type McsLock<T> = Lock<RawMcsLock, T>;
+
+pub type SpinLockGuard<'a, T> = LockGuard<'a, RawSpinLock, T>;
+pub type SpinLockIrqGuard<'a, T> = LockIrqGuard<'a, RawSpinLock, T>;
+
+/*
+ 加锁算法
+
+ acquire 返回的 Token 由守卫保存, 解锁时原样交还 (MCS 锁用它记录排队节点)
+ 实现者必须保证 acquire/try_acquire 成功之后到 release 之前没有其它持有者,
+ Lock 的内存安全依赖于此
+*/
+pub trait RawLock {
+    type Token: Copy;
+
+    const INIT: Self;
+
+    fn acquire(&self, wait: &mut SpinWait) -> Self::Token;
+
+    fn try_acquire(&self) -> Option<Self::Token>;
+
+    /*
+     token 必须来自本锁上一次成功的 acquire/try_acquire
+    */
+    unsafe fn release(&self, token: Self::Token);
+
+    fn is_locked(&self) -> bool;
+}
+
+pub struct Lock<R: RawLock, T: ?Sized> {
+    raw: R,
+    // 持有者 hart id + 1, 0 表示未被持有
+    #[cfg(debug_assertions)]
+    owner: AtomicUsize,
+    #[cfg(debug_assertions)]
+    class: lockdep::Key,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<R: RawLock + Sync, T: ?Sized + Send> Sync for Lock<R, T> {}
+unsafe impl<R: RawLock + Send, T: ?Sized + Send> Send for Lock<R, T> {}
+
+impl<R: RawLock, T> Lock<R, T> {
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            raw: R::INIT,
+            #[cfg(debug_assertions)]
+            owner: AtomicUsize::new(0),
+            #[cfg(debug_assertions)]
+            class: lockdep::Key::new(None, Location::caller()),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    // 锁类由调用位置与 name 共同决定
+    #[cfg_attr(debug_assertions, track_caller)]
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    pub const fn new_with_class(data: T, name: &'static str) -> Self {
+        Self {
+            raw: R::INIT,
+            #[cfg(debug_assertions)]
+            owner: AtomicUsize::new(0),
+            #[cfg(debug_assertions)]
+            class: lockdep::Key::new(Some(name), Location::caller()),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<R: RawLock, T: ?Sized> Lock<R, T> {
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn lock(&self) -> LockGuard<'_, R, T> {
+        self.check_recursion();
+        self.lockdep_acquire(false);
+        let token = self.raw.acquire(&mut self.spin_wait());
+        self.set_owner();
+        LockGuard { lock: self, token }
+    }
+
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn try_lock(&self) -> Option<LockGuard<'_, R, T>> {
+        let token = self.raw.try_acquire()?;
+        self.lockdep_acquire(true);
+        self.set_owner();
+        Some(LockGuard { lock: self, token })
+    }
+
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn lock_irqsave(&self) -> LockIrqGuard<'_, R, T> {
+        let irq_enabled = trap::local_irq_save();
+        let guard = self.lock();
+        let token = guard.token;
+        mem::forget(guard);
+        LockIrqGuard { lock: self, token, irq_enabled }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.raw.is_locked()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /*
+     不加锁直接访问数据, 只用于 panic 这类持有者再也不会释放锁的场合,
+     解引用前调用者自己保证不会与持有者同时访问造成问题
+    */
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    fn unlock(&self, token: R::Token) {
+        self.clear_owner();
+        self.lockdep_release();
+        unsafe { self.raw.release(token) };
+    }
+
+    #[cfg(debug_assertions)]
+    fn check_recursion(&self) {
+        let me = hart::current_id() + 1;
+        if self.owner.load(Ordering::Relaxed) == me {
+            panic!("spinlock {:p} locked recursively on hart {}", self, me - 1);
+        }
+    }
+
+    // 超时报告中的等待位置是调用 lock() 的位置
+    #[cfg(debug_assertions)]
+    #[track_caller]
+    fn spin_wait(&self) -> SpinWait<'_> {
+        SpinWait::new(self).with_holder(&self.owner)
+    }
+
+    #[cfg(debug_assertions)]
+    #[track_caller]
+    fn lockdep_acquire(&self, trylock: bool) {
+        lockdep::acquire(self.class, Location::caller(), trylock);
+    }
+
+    #[cfg(debug_assertions)]
+    fn lockdep_release(&self) {
+        lockdep::release(self.class);
+    }
+
+    #[cfg(debug_assertions)]
+    fn set_owner(&self) {
+        self.owner.store(hart::current_id() + 1, Ordering::Relaxed);
+    }
+
+    #[cfg(debug_assertions)]
+    fn clear_owner(&self) {
+        self.owner.store(0, Ordering::Relaxed);
+    }
+
+    // 持有者的 hart id
+    #[cfg(debug_assertions)]
+    pub fn owner(&self) -> Option<usize> {
+        self.owner.load(Ordering::Relaxed).checked_sub(1)
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn check_recursion(&self) {}
+
+    #[cfg(not(debug_assertions))]
+    fn spin_wait(&self) -> SpinWait<'_> {
+        SpinWait::new(self)
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn lockdep_acquire(&self, _trylock: bool) {}
+
+    #[cfg(not(debug_assertions))]
+    fn lockdep_release(&self) {}
+
+    #[cfg(not(debug_assertions))]
+    fn set_owner(&self) {}
+
+    #[cfg(not(debug_assertions))]
+    fn clear_owner(&self) {}
+
+    #[cfg(not(debug_assertions))]
+    pub fn owner(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl<T: ?Sized> Lock<RawSpinLock, T> {
+    /*
+     强制解锁, 只用于 panic 等无法正常释放守卫的场合
+     调用者保证之后不再使用原来的守卫
+     排队锁的持有者状态保存在守卫里, 无法强制解锁
+    */
+    pub unsafe fn force_unlock(&self) {
+        self.unlock(());
+    }
+}
+
+pub struct LockGuard<'a, R: RawLock, T: ?Sized> {
+    lock: &'a Lock<R, T>,
+    token: R::Token,
+}
+
+impl<R: RawLock, T: ?Sized> Deref for LockGuard<'_, R, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<R: RawLock, T: ?Sized> DerefMut for LockGuard<'_, R, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<R: RawLock, T: ?Sized> Drop for LockGuard<'_, R, T> {
+    fn drop(&mut self) {
+        self.lock.unlock(self.token);
+    }
+}
+
+pub struct LockIrqGuard<'a, R: RawLock, T: ?Sized> {
+    lock: &'a Lock<R, T>,
+    token: R::Token,
+    irq_enabled: bool,
+}
+
+impl<R: RawLock, T: ?Sized> Deref for LockIrqGuard<'_, R, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<R: RawLock, T: ?Sized> DerefMut for LockIrqGuard<'_, R, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+// 先解锁, 再恢复中断状态
+impl<R: RawLock, T: ?Sized> Drop for LockIrqGuard<'_, R, T> {
+    fn drop(&mut self) {
+        self.lock.unlock(self.token);
+        trap::local_irq_restore(self.irq_enabled);
+    }
+}
@@ -0,0 +1,78 @@
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering, fence};
+
+use super::{RawLock, RawSpinLock, SpinWait};
+use crate::trap;
+
+/*
+ 顺序锁 (seqlock)
+
+ 读者不加锁: 读取前后各读一次序号, 序号为奇数 (写入中) 或前后不一致时重读,
+ 因此读者不会阻塞写者, 适合时间基准这类很小、读远多于写的记录
+ T 必须是 Copy, 读者拿到的是一份完整的拷贝
+
+ 写者之间用自旋锁互斥, 并在写入期间关闭本 hart 的中断,
+ 否则陷入处理中的读者会在同一 hart 上永远等待写入结束
+
+ Also see:
+ Glenda/kernel/src/tests/seqlock.rs
+*/
+pub struct SeqLock<T: Copy> {
+    seq: AtomicUsize,
+    writer: RawSpinLock,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+unsafe impl<T: Copy + Send> Send for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self { seq: AtomicUsize::new(0), writer: RawSpinLock::INIT, data: UnsafeCell::new(data) }
+    }
+
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn read(&self) -> T {
+        let mut wait = SpinWait::new(self);
+        loop {
+            let start = self.seq.load(Ordering::Acquire);
+            if start & 1 != 0 {
+                wait.spin();
+                continue;
+            }
+            // 可能读到写到一半的数据, 序号校验失败时丢弃
+            let value = unsafe { ptr::read_volatile(self.data.get()) };
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == start {
+                return value;
+            }
+        }
+    }
+
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn write(&self, update: impl FnOnce(&mut T)) {
+        let irq_enabled = trap::local_irq_save();
+        self.writer.acquire(&mut SpinWait::new(self));
+
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        // 序号先于数据对读者可见
+        fence(Ordering::Release);
+        update(unsafe { &mut *self.data.get() });
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+
+        unsafe { self.writer.release(()) };
+        trap::local_irq_restore(irq_enabled);
+    }
+
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn set(&self, value: T) {
+        self.write(|data| *data = value);
+    }
+
+    // 已完成的写入次数
+    pub fn sequence(&self) -> usize {
+        self.seq.load(Ordering::Acquire) / 2
+    }
+}
@@ -0,0 +1,35 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{RawLock, SpinWait};
+
+// test-and-test-and-set 自旋锁
+pub struct RawSpinLock {
+    locked: AtomicBool,
+}
+
+impl RawLock for RawSpinLock {
+    type Token = ();
+
+    const INIT: Self = Self { locked: AtomicBool::new(false) };
+
+    fn acquire(&self, wait: &mut SpinWait) {
+        // 先只读等待锁被释放, 避免反复 swap 争抢缓存行
+        while self.locked.swap(true, Ordering::Acquire) {
+            while self.locked.load(Ordering::Relaxed) {
+                wait.spin();
+            }
+        }
+    }
+
+    fn try_acquire(&self) -> Option<()> {
+        (!self.locked.swap(true, Ordering::Acquire)).then_some(())
+    }
+
+    unsafe fn release(&self, _token: ()) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
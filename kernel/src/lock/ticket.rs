@@ -0,0 +1,50 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::{RawLock, SpinWait};
+
+/*
+ 票据锁: 加锁时领取 next 作为票号, 等到 serving 等于票号时获得锁,
+ 解锁时 serving 加一, 因此按领号顺序 (FIFO) 获得锁
+ 票号回绕不影响正确性, 只要同时等待的 hart 少于 2^32 个
+*/
+pub struct RawTicketLock {
+    next: AtomicU32,
+    serving: AtomicU32,
+}
+
+impl RawLock for RawTicketLock {
+    type Token = ();
+
+    const INIT: Self = Self { next: AtomicU32::new(0), serving: AtomicU32::new(0) };
+
+    fn acquire(&self, wait: &mut SpinWait) {
+        let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+        while self.serving.load(Ordering::Acquire) != ticket {
+            wait.spin();
+        }
+    }
+
+    // 只在没有人持有或排队时领号
+    fn try_acquire(&self) -> Option<()> {
+        let serving = self.serving.load(Ordering::Relaxed);
+        self.next
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| ())
+    }
+
+    // 只有持有者会修改 serving
+    unsafe fn release(&self, _token: ()) {
+        let serving = self.serving.load(Ordering::Relaxed);
+        self.serving.store(serving.wrapping_add(1), Ordering::Release);
+    }
+
+    fn is_locked(&self) -> bool {
+        self.next.load(Ordering::Relaxed) != self.serving.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,150 @@
+use core::hint::spin_loop;
+use core::marker::PhantomData;
+use core::sync::atomic::AtomicUsize;
+
+#[cfg(debug_assertions)]
+use core::panic::Location;
+#[cfg(debug_assertions)]
+use core::sync::atomic::Ordering;
+
+#[cfg(debug_assertions)]
+use crate::backtrace::Backtrace;
+#[cfg(debug_assertions)]
+use crate::hart;
+#[cfg(debug_assertions)]
+use crate::printk::{ANSI_RED, ANSI_RESET};
+#[cfg(debug_assertions)]
+use crate::timer;
+
+/*
+ 自旋等待
+
+ 所有自旋循环都通过 SpinWait::spin() 等待, debug 构建中每隔 CHECK_INTERVAL 次
+ 检查一次 time CSR, 等待超过 SPIN_TIMEOUT_MS 时报告等待位置、锁地址、持有者和栈回溯, 之后继续等待
+ 报告直接写 UART 而不经过 printk, 因为等待的可能就是 PRINTK_LOCK
+
+ release 构建中 spin() 只是 spin_loop()
+
+   let mut wait = SpinWait::new(&FLAG);
+   while !FLAG.load(Ordering::Acquire) {
+       wait.spin();
+   }
+
+ Also see:
+ Glenda/kernel/src/watchdog.rs
+*/
+#[cfg(debug_assertions)]
+const SPIN_TIMEOUT_MS: usize = 5000;
+#[cfg(debug_assertions)]
+const CHECK_INTERVAL: usize = 1024;
+
+pub struct SpinWait<'a> {
+    #[cfg(debug_assertions)]
+    site: &'static Location<'static>,
+    #[cfg(debug_assertions)]
+    target: *const (),
+    // 持有者 hart id + 1, 0 表示未知
+    #[cfg(debug_assertions)]
+    holder: Option<&'a AtomicUsize>,
+    #[cfg(debug_assertions)]
+    timeout_ms: usize,
+    // 第一次检查时的 time, 0 表示尚未开始计时
+    #[cfg(debug_assertions)]
+    start: usize,
+    #[cfg(debug_assertions)]
+    spins: usize,
+    #[cfg(debug_assertions)]
+    reported: bool,
+    _marker: PhantomData<&'a AtomicUsize>,
+}
+
+#[cfg(debug_assertions)]
+impl<'a> SpinWait<'a> {
+    #[track_caller]
+    pub fn new<T: ?Sized>(target: *const T) -> Self {
+        Self {
+            site: Location::caller(),
+            target: target.cast(),
+            holder: None,
+            timeout_ms: SPIN_TIMEOUT_MS,
+            start: 0,
+            spins: 0,
+            reported: false,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn with_holder(mut self, holder: &'a AtomicUsize) -> Self {
+        self.holder = Some(holder);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout_ms: usize) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    pub fn spin(&mut self) {
+        spin_loop();
+        self.spins += 1;
+        if self.reported || !self.spins.is_multiple_of(CHECK_INTERVAL) {
+            return;
+        }
+        let now = timer::now();
+        if self.start == 0 {
+            self.start = now;
+            return;
+        }
+        let waited = timer::ticks_to_ms(now.wrapping_sub(self.start));
+        if waited >= self.timeout_ms {
+            self.reported = true;
+            self.report(waited);
+        }
+    }
+
+    // 是否已经超时并报告过
+    pub fn timed_out(&self) -> bool {
+        self.reported
+    }
+
+    fn report(&self, waited: usize) {
+        let holder = self.holder.and_then(|holder| holder.load(Ordering::Relaxed).checked_sub(1));
+        driver_uart::print!(
+            "{}spin timeout{}: hart {} waited {} ms at {} for {:p}",
+            ANSI_RED,
+            ANSI_RESET,
+            hart::current_id(),
+            waited,
+            self.site,
+            self.target
+        );
+        match holder {
+            Some(holder) => driver_uart::print!(", held by hart {}\n", holder),
+            None => driver_uart::print!("\n"),
+        }
+        driver_uart::print!("{}\n", Backtrace::capture());
+    }
+}
+
+#[cfg(not(debug_assertions))]
+impl<'a> SpinWait<'a> {
+    pub fn new<T: ?Sized>(_target: *const T) -> Self {
+        Self { _marker: PhantomData }
+    }
+
+    pub fn with_holder(self, _holder: &'a AtomicUsize) -> Self {
+        self
+    }
+
+    pub fn with_timeout(self, _timeout_ms: usize) -> Self {
+        self
+    }
+
+    pub fn spin(&mut self) {
+        spin_loop();
+    }
+
+    pub fn timed_out(&self) -> bool {
+        false
+    }
+}
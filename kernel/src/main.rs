@@ -1,21 +1,42 @@
 #![no_std]
 #![no_main]
 
+extern crate alloc;
+
+mod backtrace;
 mod dtb;
+mod hart;
 mod init;
+mod ipi;
+mod ksyms;
 mod lock;
 mod logo;
+mod mm;
+mod panic;
+mod percpu;
 mod printk;
+mod rcu;
 #[cfg(feature = "tests")]
 mod tests;
+mod timer;
+mod trace;
+mod trap;
+mod watchdog;
 
 use core::panic::PanicInfo;
-use init::init_harts;
+use init::{init_harts, init_ipi, init_mm, init_percpu, init_rcu, init_timer, init_trace};
 use logo::LOGO;
-use printk::{ANSI_BLUE, ANSI_RED, ANSI_RESET};
+use printk::{ANSI_BLUE, ANSI_RESET};
 use riscv::asm::wfi;
 #[cfg(feature = "tests")]
-use tests::{run_printk_tests, run_spinlock_tests};
+use tests::{
+    run_barrier_tests, run_heap_tests, run_lockdep_tests, run_percpu_tests, run_printk_tests,
+    run_rcu_tests, run_rwlock_tests, run_seqlock_tests, run_slab_tests, run_spinlock_tests,
+    run_trace_tests, run_watchdog_tests,
+};
+
+const UART_CONSOLE: printk::Console =
+    printk::Console { name: "uart", write: driver_uart::write_bytes };
 
 /*
  为了便捷，M-mode 固件与 M->S 的降权交给 OpenSBI，程序只负责 S-mode 下的内核
@@ -31,30 +52,47 @@ use tests::{run_printk_tests, run_spinlock_tests};
 #[unsafe(no_mangle)]
 pub extern "C" fn glenda_main(hartid: usize, dtb: *const u8) -> ! {
     // 解析设备树
-    let dtb_result = dtb::init(dtb);
+    let dtb_result = dtb::init(mm::phys_to_virt(dtb as usize) as *const u8);
 
-    // 初始化串口驱动
+    // 初始化串口驱动, MMIO 通过直接映射访问
     let uart_cfg = dtb::uart_config().unwrap_or(driver_uart::DEFAULT_QEMU_VIRT);
-    driver_uart::init(uart_cfg);
+    driver_uart::init(uart_cfg.with_base(mm::phys_to_virt(uart_cfg.base())));
+    // 之前的输出都在日志缓冲区中, 注册时重放
+    if hart::is_boot() {
+        printk::register_console(UART_CONSOLE);
+    }
 
     // 启动信息
-    if hartid == 0 {
+    if hart::is_boot() {
         match dtb_result {
             Ok(_) => {
-                printk!("Device tree blob at {:p}", dtb);
-                printk!(
+                pr_info!("device tree blob at {:p}", dtb);
+                pr_info!(
                     "UART in use: base=0x{:x}, thr=0x{:x}, lsr=0x{:x}",
                     uart_cfg.base(),
                     uart_cfg.thr_offset(),
                     uart_cfg.lsr_offset()
                 );
-                printk!("{} harts detected", dtb::hart_count());
+                pr_info!("{} harts detected, booting on hart {}", hart::cpu_count(), hartid);
+                if dtb::ignored_hart_count() > 0 {
+                    pr_warn!(
+                        "{} enabled harts ignored: hart ids must be below {} (rebuild with a larger GLENDA_MAX_HARTS)",
+                        dtb::ignored_hart_count(),
+                        hart::MAX_HARTS
+                    );
+                }
+                if hart::cpu_id(hartid).is_none() {
+                    pr_warn!("boot hart {} is not listed in the device tree", hartid);
+                }
             }
             Err(err) => {
-                printk!("Device tree parsing failed: {:?}", err);
-                printk!("Falling back to QEMU-virt default UART @ 0x10000000");
+                pr_err!("device tree parsing failed: {:?}", err);
+                pr_warn!("falling back to QEMU-virt default UART @ 0x10000000");
             }
         }
+        // 会泄露 KASLR 偏移, 只在调试构建中打印
+        #[cfg(debug_assertions)]
+        mm::print_layout();
         printk!("{}", LOGO);
         printk!("{}Glenda microkernel booting{}", ANSI_BLUE, ANSI_RESET);
     }
@@ -63,22 +101,37 @@ pub extern "C" fn glenda_main(hartid: usize, dtb: *const u8) -> ! {
     #[cfg(feature = "tests")]
     {
         run_printk_tests(hartid);
+        run_heap_tests(hartid);
+        run_slab_tests(hartid);
+        run_percpu_tests(hartid);
         run_spinlock_tests(hartid);
+        run_barrier_tests(hartid);
+        run_rwlock_tests(hartid);
+        run_seqlock_tests(hartid);
+        run_rcu_tests(hartid);
+        run_lockdep_tests(hartid);
+        run_watchdog_tests(hartid);
+        run_trace_tests(hartid);
     }
 
     loop {
+        watchdog::touch();
+        rcu::quiescent();
         wfi();
     }
 }
 
 #[panic_handler]
 pub fn panic(info: &PanicInfo) -> ! {
-    printk!("{}PANIC{}: {}", ANSI_RED, ANSI_RESET, info);
-    loop {
-        wfi();
-    }
+    panic::handle(info)
 }
 
 fn init(hartid: usize, dtb: *const u8) {
+    init_percpu(hartid);
+    init_mm(hartid, dtb);
     init_harts(hartid, dtb);
+    init_rcu();
+    init_trace();
+    init_ipi();
+    init_timer();
 }
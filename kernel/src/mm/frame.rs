@@ -0,0 +1,142 @@
+#![allow(dead_code)]
+
+use core::ptr;
+
+use super::{PAGE_SIZE, phys_to_virt};
+use crate::lock::SpinLock;
+
+/*
+ 物理页帧分配器 (伙伴系统)
+
+ 空闲块按阶 (2^order 个页帧) 挂在各自的单链表上,
+ 链表节点直接写在空闲页帧开头, 通过直接映射访问
+ 释放时与伙伴块合并, 最大块为 2^(MAX_ORDER - 1) 个页帧 (4 MiB)
+*/
+pub const MAX_ORDER: usize = 11;
+
+struct FreeBlock {
+    next: *mut FreeBlock,
+}
+
+struct FrameAllocator {
+    free: [*mut FreeBlock; MAX_ORDER],
+    free_frames: usize,
+    total_frames: usize,
+}
+
+// 链表节点只在持有 FRAMES 锁时访问
+unsafe impl Send for FrameAllocator {}
+
+static FRAMES: SpinLock<FrameAllocator> = SpinLock::new(FrameAllocator::new());
+
+const fn block_size(order: usize) -> usize {
+    PAGE_SIZE << order
+}
+
+fn block_ptr(pa: usize) -> *mut FreeBlock {
+    phys_to_virt(pa) as *mut FreeBlock
+}
+
+fn block_pa(block: *mut FreeBlock) -> usize {
+    super::virt_to_phys(block as usize)
+}
+
+impl FrameAllocator {
+    const fn new() -> Self {
+        Self { free: [ptr::null_mut(); MAX_ORDER], free_frames: 0, total_frames: 0 }
+    }
+
+    fn push(&mut self, pa: usize, order: usize) {
+        let block = block_ptr(pa);
+        unsafe { block.write(FreeBlock { next: self.free[order] }) };
+        self.free[order] = block;
+        self.free_frames += 1 << order;
+    }
+
+    fn pop(&mut self, order: usize) -> Option<usize> {
+        let block = self.free[order];
+        if block.is_null() {
+            return None;
+        }
+        self.free[order] = unsafe { (*block).next };
+        self.free_frames -= 1 << order;
+        Some(block_pa(block))
+    }
+
+    // 从链表中摘下指定的块, 用于合并伙伴
+    fn remove(&mut self, pa: usize, order: usize) -> bool {
+        let target = block_ptr(pa);
+        let mut link = &raw mut self.free[order];
+        unsafe {
+            while !(*link).is_null() {
+                if *link == target {
+                    *link = (*target).next;
+                    self.free_frames -= 1 << order;
+                    return true;
+                }
+                link = &raw mut (**link).next;
+            }
+        }
+        false
+    }
+
+    // 把 [start, end) 切成尽可能大的对齐块加入空闲链表
+    fn add_range(&mut self, start: usize, end: usize) {
+        let mut start = start.next_multiple_of(PAGE_SIZE);
+        let end = end & !(PAGE_SIZE - 1);
+        while start < end {
+            let mut order = MAX_ORDER - 1;
+            while !start.is_multiple_of(block_size(order)) || start + block_size(order) > end {
+                order -= 1;
+            }
+            self.push(start, order);
+            self.total_frames += 1 << order;
+            start += block_size(order);
+        }
+    }
+
+    fn alloc(&mut self, order: usize) -> Option<usize> {
+        let found = (order..MAX_ORDER).find(|&o| !self.free[o].is_null())?;
+        let pa = self.pop(found)?;
+        // 拆分多余的部分, 高地址的一半放回空闲链表
+        for o in (order..found).rev() {
+            self.push(pa + block_size(o), o);
+        }
+        Some(pa)
+    }
+
+    fn free(&mut self, mut pa: usize, mut order: usize) {
+        while order < MAX_ORDER - 1 {
+            let buddy = pa ^ block_size(order);
+            if !self.remove(buddy, order) {
+                break;
+            }
+            pa = pa.min(buddy);
+            order += 1;
+        }
+        self.push(pa, order);
+    }
+}
+
+pub fn add_range(start: usize, end: usize) {
+    FRAMES.lock().add_range(start, end);
+}
+
+// 分配 2^order 个物理上连续的页帧, 返回物理地址, 内容未清零
+pub fn alloc_frames(order: usize) -> Option<usize> {
+    if order >= MAX_ORDER {
+        return None;
+    }
+    FRAMES.lock().alloc(order)
+}
+
+// 调用者保证 pa 来自 alloc_frames(order), 且之后不再被使用
+pub unsafe fn free_frames(pa: usize, order: usize) {
+    FRAMES.lock().free(pa, order);
+}
+
+// (空闲页帧数, 总页帧数)
+pub fn stats() -> (usize, usize) {
+    let frames = FRAMES.lock();
+    (frames.free_frames, frames.total_frames)
+}
@@ -0,0 +1,184 @@
+#![allow(dead_code)]
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::{align_of, size_of};
+use core::ptr;
+
+use super::frame::{self, MAX_ORDER};
+use super::{PAGE_SIZE, phys_to_virt};
+use crate::lock::SpinLock;
+use crate::pr_err;
+
+/*
+ 内核堆
+
+ 空闲块按地址排序挂在单链表上, 首次适配分配, 释放时与相邻空闲块合并
+ 堆空间不足时向页帧分配器申请新的连续块 (至少 HEAP_GROW_MIN),
+ 申请到的内存不会归还给页帧分配器
+*/
+const HEAP_GROW_MIN: usize = 64 * 1024;
+
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/*
+ 所有块的地址和大小都是 GRANULE 的整数倍,
+ 因此切分后剩下的碎片要么为空, 要么足够放下一个 FreeBlock
+*/
+const GRANULE: usize = size_of::<FreeBlock>().next_power_of_two();
+const _: () = assert!(GRANULE >= align_of::<FreeBlock>());
+
+struct FreeList {
+    head: *mut FreeBlock,
+    // 从页帧分配器拿到的总字节数
+    size: usize,
+    used: usize,
+}
+
+unsafe impl Send for FreeList {}
+
+// 分配的实际大小与对齐
+fn adjust(layout: Layout) -> (usize, usize) {
+    (layout.size().max(1).next_multiple_of(GRANULE), layout.align().max(GRANULE))
+}
+
+impl FreeList {
+    const fn new() -> Self {
+        Self { head: ptr::null_mut(), size: 0, used: 0 }
+    }
+
+    // 按地址顺序插入 [addr, addr + size), 并与前后的空闲块合并
+    unsafe fn insert(&mut self, addr: usize, size: usize) {
+        unsafe {
+            let mut prev: *mut FreeBlock = ptr::null_mut();
+            let mut next = self.head;
+            while !next.is_null() && (next as usize) < addr {
+                prev = next;
+                next = (*next).next;
+            }
+
+            let block = addr as *mut FreeBlock;
+            block.write(FreeBlock { size, next });
+            if !next.is_null() && addr + size == next as usize {
+                (*block).size += (*next).size;
+                (*block).next = (*next).next;
+            }
+
+            if prev.is_null() {
+                self.head = block;
+            } else if prev as usize + (*prev).size == addr {
+                (*prev).size += (*block).size;
+                (*prev).next = (*block).next;
+            } else {
+                (*prev).next = block;
+            }
+        }
+    }
+
+    unsafe fn alloc(&mut self, size: usize, align: usize) -> *mut u8 {
+        unsafe {
+            let mut prev: *mut FreeBlock = ptr::null_mut();
+            let mut block = self.head;
+            while !block.is_null() {
+                let start = block as usize;
+                let end = start + (*block).size;
+                let alloc_start = start.next_multiple_of(align);
+                let alloc_end = alloc_start + size;
+
+                if alloc_end <= end {
+                    let next = (*block).next;
+                    if prev.is_null() {
+                        self.head = next;
+                    } else {
+                        (*prev).next = next;
+                    }
+                    if alloc_start != start {
+                        self.insert(start, alloc_start - start);
+                    }
+                    if alloc_end != end {
+                        self.insert(alloc_end, end - alloc_end);
+                    }
+                    self.used += size;
+                    return alloc_start as *mut u8;
+                }
+
+                prev = block;
+                block = (*block).next;
+            }
+        }
+        ptr::null_mut()
+    }
+
+    // 向页帧分配器申请至少能满足 size/align 的一块内存
+    fn grow(&mut self, size: usize, align: usize) -> bool {
+        let bytes = (size + align).max(HEAP_GROW_MIN);
+        let order = bytes.div_ceil(PAGE_SIZE).next_power_of_two().trailing_zeros() as usize;
+        if order >= MAX_ORDER {
+            return false;
+        }
+        match frame::alloc_frames(order) {
+            Some(pa) => {
+                let bytes = PAGE_SIZE << order;
+                unsafe { self.insert(phys_to_virt(pa), bytes) };
+                self.size += bytes;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct KernelHeap {
+    inner: SpinLock<FreeList>,
+}
+
+#[global_allocator]
+static HEAP: KernelHeap = KernelHeap { inner: SpinLock::new(FreeList::new()) };
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (size, align) = adjust(layout);
+        let mut heap = self.inner.lock();
+        loop {
+            let ptr = unsafe { heap.alloc(size, align) };
+            if !ptr.is_null() {
+                return ptr;
+            }
+            if !heap.grow(size, align) {
+                break;
+            }
+        }
+
+        // 返回空指针后由 alloc::alloc::handle_alloc_error 进入 panic
+        let (free_frames, total_frames) = frame::stats();
+        pr_err!(
+            "kernel heap out of memory: request {} bytes (align {}), heap {} KiB, used {} KiB, free frames {}/{}",
+            layout.size(),
+            layout.align(),
+            heap.size / 1024,
+            heap.used / 1024,
+            free_frames,
+            total_frames
+        );
+        ptr::null_mut()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = adjust(layout);
+        let mut heap = self.inner.lock();
+        unsafe { heap.insert(ptr as usize, size) };
+        heap.used -= size;
+    }
+}
+
+pub struct HeapStats {
+    pub size: usize,
+    pub used: usize,
+}
+
+pub fn stats() -> HeapStats {
+    let heap = HEAP.inner.lock();
+    HeapStats { size: heap.size, used: heap.used }
+}
@@ -0,0 +1,234 @@
+use core::arch::asm;
+use core::slice;
+
+use super::BootLayout;
+
+/*
+ KASLR: 内核镜像、物理内存直接映射和内核栈窗口各自随机放在一段根页表项里
+
+   根页表项     用途
+   256..=383    直接映射 (占 64 项)
+   384..=447    内核栈窗口 (占 1 项)
+   448..=511    内核镜像窗口 (占 2 项)
+
+ 早期页表只使用 1 GiB 大页, 因此随机化的粒度也是 1 GiB,
+ 镜像在大页内的偏移与物理加载地址保持一致
+
+ 熵来源 (全部混合):
+   - 设备树 /chosen/rng-seed 与 /chosen/kaslr-seed, 读取后清零
+   - Zkr 扩展的 seed CSR
+ 没有任何熵, 或 bootargs 中带有 nokaslr 时使用固定布局
+
+ kaslr_early_init 在重定位之前以物理地址运行:
+ 不能访问任何需要重定位的数据 (全局指针、trait 对象、格式化等)
+
+ Also see:
+ Glenda/kernel/src/boot.S
+*/
+const GIGA: usize = 1 << 30;
+
+struct SlotRange {
+    first: usize,
+    count: usize,
+    default: usize,
+}
+
+impl SlotRange {
+    fn pick(&self, entropy: &mut u64) -> usize {
+        let count = self.count as u64;
+        let slot = self.first + (*entropy % count) as usize;
+        *entropy /= count;
+        slot
+    }
+}
+
+const PHYS_MAP_SLOTS: SlotRange = SlotRange { first: 256, count: 65, default: 256 };
+const STACK_SLOTS: SlotRange = SlotRange { first: 384, count: 64, default: 384 };
+const KERNEL_SLOTS: SlotRange = SlotRange { first: 448, count: 63, default: 510 };
+
+// 根页表项 -> 高半区虚拟地址 (符号扩展)
+const fn slot_addr(slot: usize) -> usize {
+    0xffff_ff80_0000_0000 | (slot << 30)
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kaslr_early_init(dtb: *mut u8, load_pa: usize, layout: *mut BootLayout) {
+    let giga_base = load_pa & !(GIGA - 1);
+
+    let mut pool = EntropyPool::new();
+    let mut nokaslr = false;
+    unsafe {
+        for_each_chosen_prop(dtb, |name, value| match name {
+            b"rng-seed" | b"kaslr-seed" => {
+                pool.feed(value);
+                value.fill(0);
+            }
+            b"bootargs" => nokaslr = value.windows(7).any(|word| word == b"nokaslr"),
+            _ => {}
+        });
+    }
+    if let Some(seed) = read_seed_csr() {
+        pool.feed(&seed.to_le_bytes());
+    }
+
+    let seed = if nokaslr { None } else { pool.finish() };
+    let (phys_map, stack, kernel) = match seed {
+        Some(mut entropy) => (
+            PHYS_MAP_SLOTS.pick(&mut entropy),
+            STACK_SLOTS.pick(&mut entropy),
+            KERNEL_SLOTS.pick(&mut entropy),
+        ),
+        None => (PHYS_MAP_SLOTS.default, STACK_SLOTS.default, KERNEL_SLOTS.default),
+    };
+
+    unsafe {
+        layout.write(BootLayout {
+            kernel_virt_offset: slot_addr(kernel).wrapping_sub(giga_base),
+            phys_map_base: slot_addr(phys_map),
+            stack_base: slot_addr(stack),
+            kaslr: seed.is_some() as usize,
+        });
+    }
+}
+
+struct EntropyPool {
+    state: u64,
+    fed: bool,
+}
+
+impl EntropyPool {
+    const fn new() -> Self {
+        Self { state: 0xcbf2_9ce4_8422_2325, fed: false }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.state = (self.state ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        self.fed |= !bytes.is_empty();
+    }
+
+    // splitmix64 收尾, 让每一位都依赖全部输入
+    fn finish(&self) -> Option<u64> {
+        if !self.fed {
+            return None;
+        }
+        let mut z = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Some(z ^ (z >> 31))
+    }
+}
+
+/*
+ Zkr seed CSR[1], 每次读取最多提供 16 位熵
+
+ S-mode 只有在 M-mode 设置了 mseccfg.SSEED 时才能访问,
+ 否则触发非法指令异常, 由 boot.S 的 early_trap 跳过该指令并保留预置的 DEAD 状态
+
+ [1]: https://github.com/riscv/riscv-crypto, Section 4.1 "Entropy Source"
+*/
+const CSR_SEED_OPST_SHIFT: usize = 30;
+const OPST_ES16: usize = 0b10;
+const OPST_DEAD: usize = 0b11;
+const SEED_POLL_LIMIT: usize = 1024;
+
+fn read_seed_csr() -> Option<u64> {
+    let mut seed = 0u64;
+    for _ in 0..4 {
+        let mut polls = 0;
+        let entropy = loop {
+            let mut value = OPST_DEAD << CSR_SEED_OPST_SHIFT;
+            unsafe { asm!("csrrw {0}, 0x015, zero", inout(reg) value) };
+            match (value >> CSR_SEED_OPST_SHIFT) & 0b11 {
+                OPST_ES16 => break value & 0xffff,
+                OPST_DEAD => return None,
+                // BIST / WAIT
+                _ if polls >= SEED_POLL_LIMIT => return None,
+                _ => polls += 1,
+            }
+        };
+        seed = (seed << 16) | entropy as u64;
+    }
+    Some(seed)
+}
+
+/*
+ 早期阶段还不能使用 fdt crate, 这里直接遍历结构块, 只访问 /chosen 下的属性
+
+ See SPEC: https://devicetree-specification.readthedocs.io/en/stable/flattened-format.html
+*/
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_MAX_NAME: usize = 256;
+
+unsafe fn be32(p: *const u8) -> u32 {
+    u32::from_be(unsafe { p.cast::<u32>().read_unaligned() })
+}
+
+unsafe fn c_str<'a>(p: *const u8) -> &'a [u8] {
+    let mut len = 0;
+    while len < FDT_MAX_NAME && unsafe { *p.add(len) } != 0 {
+        len += 1;
+    }
+    unsafe { slice::from_raw_parts(p, len) }
+}
+
+const fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+unsafe fn for_each_chosen_prop(dtb: *mut u8, mut visit: impl FnMut(&[u8], &mut [u8])) {
+    if dtb.is_null() || unsafe { be32(dtb) } != FDT_MAGIC {
+        return;
+    }
+    let (structs, strings, size) = unsafe {
+        (
+            dtb.add(be32(dtb.add(8)) as usize),
+            dtb.add(be32(dtb.add(12)) as usize),
+            be32(dtb.add(36)) as usize,
+        )
+    };
+
+    let mut offset = 0;
+    let mut depth = 0usize;
+    let mut in_chosen = false;
+    while size.saturating_sub(offset) >= 4 {
+        let token = unsafe { be32(structs.add(offset)) };
+        offset += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                // 属性总是排在子节点之前, 进入 /chosen 的子节点时已经遍历完毕
+                if in_chosen {
+                    return;
+                }
+                let name = unsafe { c_str(structs.add(offset)) };
+                offset += align4(name.len() + 1);
+                depth += 1;
+                in_chosen = depth == 2 && name == b"chosen";
+            }
+            FDT_END_NODE => {
+                if in_chosen {
+                    return;
+                }
+                depth = depth.saturating_sub(1);
+            }
+            FDT_PROP => {
+                let (len, name_offset) =
+                    unsafe { (be32(structs.add(offset)) as usize, be32(structs.add(offset + 4))) };
+                offset += 8;
+                if in_chosen && offset.saturating_add(len) <= size {
+                    let name = unsafe { c_str(strings.add(name_offset as usize)) };
+                    let value = unsafe { slice::from_raw_parts_mut(structs.add(offset), len) };
+                    visit(name, value);
+                }
+                offset = offset.saturating_add(align4(len));
+            }
+            FDT_NOP => {}
+            _ => return,
+        }
+    }
+}
@@ -0,0 +1,174 @@
+pub mod frame;
+pub mod heap;
+mod kaslr;
+pub mod slab;
+pub mod stack;
+
+use sync::OnceCell;
+
+use crate::dtb;
+use crate::dtb::Region;
+use crate::pr_info;
+
+/*
+ Sv39 内核地址空间布局 (未启用 KASLR 时)
+
+   0xffff_ffc0_0000_0000  +------------------------------+
+                          |  物理内存直接映射 (64 GiB)      |
+                          +------------------------------+
+                          |             ...              |
+   0xffff_ffe0_0000_0000  +------------------------------+
+                          |  内核栈窗口 (1 GiB)            |
+                          +------------------------------+
+                          |             ...              |
+   0xffff_ffff_8000_0000  +------------------------------+
+                          |  内核镜像 (2 GiB 窗口)          |
+   0xffff_ffff_ffff_ffff  +------------------------------+
+
+ 内核镜像的虚拟地址 = 窗口基址 + 物理加载地址在 1 GiB 内的偏移,
+ boot.S 在打开分页前调用 kaslr_early_init 决定各区域位置并完成重定位,
+ 结果记录在 boot_layout 中
+
+ Also see:
+ Glenda/kernel/src/boot.S
+ Glenda/kernel/src/linker.ld
+ Glenda/kernel/src/mm/kaslr.rs
+ Glenda/kernel/src/mm/slab.rs
+ Glenda/kernel/src/mm/stack.rs
+*/
+pub const KERNEL_LINK_BASE: usize = 0xffff_ffff_8020_0000;
+pub const PHYS_MAP_SIZE: usize = 64 << 30;
+pub const PAGE_SIZE: usize = 4096;
+
+// 字段顺序与 boot.S 中的 BOOT_LAYOUT_* 偏移一致
+#[repr(C)]
+pub struct BootLayout {
+    kernel_virt_offset: usize,
+    phys_map_base: usize,
+    stack_base: usize,
+    kaslr: usize,
+}
+
+unsafe extern "C" {
+    static boot_layout: BootLayout;
+    static __kernel_start: u8;
+    static __kernel_end: u8;
+}
+
+fn layout() -> &'static BootLayout {
+    unsafe { &boot_layout }
+}
+
+// 物理地址 -> 直接映射区的虚拟地址
+pub fn phys_to_virt(pa: usize) -> usize {
+    debug_assert!(pa < PHYS_MAP_SIZE);
+    pa + layout().phys_map_base
+}
+
+// 直接映射区的虚拟地址 -> 物理地址
+pub fn virt_to_phys(va: usize) -> usize {
+    let pa = va - layout().phys_map_base;
+    debug_assert!(pa < PHYS_MAP_SIZE);
+    pa
+}
+
+// 内核镜像内的虚拟地址 -> 物理地址
+pub fn kernel_virt_to_phys(va: usize) -> usize {
+    va - layout().kernel_virt_offset
+}
+
+pub fn kernel_start() -> usize {
+    (&raw const __kernel_start) as usize
+}
+
+pub fn kernel_end() -> usize {
+    (&raw const __kernel_end) as usize
+}
+
+// KASLR 使内核镜像相对链接地址移动的距离
+pub fn kaslr_offset() -> usize {
+    kernel_start().wrapping_sub(KERNEL_LINK_BASE)
+}
+
+// 内核镜像内的运行地址 -> 链接地址, 用于与 ELF 中的符号对照
+pub fn link_address(va: usize) -> usize {
+    va.wrapping_sub(kaslr_offset())
+}
+
+static MM_INIT: OnceCell<()> = OnceCell::new();
+
+/*
+ 把设备树中的可用内存交给页帧分配器, 除去:
+   - 内核镜像 (含内核栈和启动页表)
+   - 设备树本身
+   - /memreserve/ 与 /reserved-memory 中的区域
+ 内核堆在第一次分配时再向页帧分配器申请内存
+*/
+pub fn init(dtb_pa: usize) {
+    MM_INIT.get_or_init(|| {
+        let reserved = dtb::reserved_regions();
+        let mut excluded = [Region::default(); dtb::MAX_REGIONS + 2];
+        excluded[0] = Region {
+            start: kernel_virt_to_phys(kernel_start()),
+            size: kernel_end() - kernel_start(),
+        };
+        excluded[1] = Region { start: dtb_pa, size: dtb::blob_size() };
+        excluded[2..2 + reserved.len()].copy_from_slice(reserved);
+        let excluded = &excluded[..2 + reserved.len()];
+
+        for memory in dtb::memory_regions() {
+            add_usable(memory.start, memory.end().min(PHYS_MAP_SIZE), excluded);
+        }
+
+        let (free, total) = frame::stats();
+        pr_info!(
+            "memory: {} KiB available in {} frames ({} free)",
+            total * PAGE_SIZE / 1024,
+            total,
+            free
+        );
+    });
+}
+
+// 把 [start, end) 中不与 excluded 重叠的部分加入页帧分配器
+fn add_usable(start: usize, end: usize, excluded: &[Region]) {
+    if start >= end {
+        return;
+    }
+    match excluded.split_first() {
+        None => frame::add_range(start, end),
+        Some((region, rest)) if region.end() <= start || region.start >= end => {
+            add_usable(start, end, rest)
+        }
+        Some((region, rest)) => {
+            add_usable(start, region.start, rest);
+            add_usable(region.end(), end, rest);
+        }
+    }
+}
+
+/*
+ 随机化后的布局只在 debug 构建中输出, 避免把偏移泄露到控制台
+
+ gdb 可以用 `symbol-file <elf> -o <kernel offset>` 加载符号,
+ 或者 `cargo xtask gdb --kaslr-offset <kernel offset>`
+*/
+#[cfg(debug_assertions)]
+pub fn print_layout() {
+    let layout = layout();
+    if layout.kaslr == 0 {
+        pr_info!("KASLR disabled (no seed or nokaslr)");
+    }
+    pr_info!(
+        "KASLR: kernel offset 0x{:x}, direct map @ 0x{:x}, kernel stacks @ 0x{:x}",
+        kaslr_offset(),
+        layout.phys_map_base,
+        layout.stack_base
+    );
+    pr_info!(
+        "kernel image at 0x{:x}-0x{:x} (phys 0x{:x})",
+        kernel_start(),
+        kernel_end(),
+        kernel_virt_to_phys(kernel_start())
+    );
+}
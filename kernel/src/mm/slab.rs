@@ -0,0 +1,378 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::frame::{self, MAX_ORDER};
+use super::{PAGE_SIZE, phys_to_virt};
+use crate::hart::{self, MAX_HARTS};
+use crate::lock::SpinLock;
+use crate::printk;
+
+/*
+ Slab 分配器 (对象缓存)
+
+ 每种定长内核对象 (线程、端点、能力槽、页表页...) 使用一个 SlabCache:
+   - slab: 从页帧分配器申请的 2^order 个连续页帧, 切成等大的对象
+   - depot: 全局空闲对象链表, 链表指针写在空闲对象开头
+   - magazine: 每个 hart 私有的空闲对象栈, 常规的分配/释放只访问本 hart 的 magazine,
+     空了或满了才批量与 depot 交换半个 magazine
+
+ slab 占用的页帧不会归还给页帧分配器
+
+ debug 构建中释放的对象会被填充为 POISON_FREE, 再次分配时检查,
+ 被改写说明有释放后使用 (use-after-free)
+
+ Also see:
+ Glenda/kernel/src/mm/frame.rs
+*/
+const MAGAZINE_SIZE: usize = 16;
+// 每个 slab 至少容纳的对象数
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+const POISON_FREE: u8 = 0x6b;
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+struct Magazine {
+    objects: [*mut u8; MAGAZINE_SIZE],
+    len: usize,
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self { objects: [ptr::null_mut(); MAGAZINE_SIZE], len: 0 }
+    }
+
+    fn pop(&mut self) -> Option<*mut u8> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.objects[self.len])
+    }
+
+    fn push(&mut self, object: *mut u8) {
+        self.objects[self.len] = object;
+        self.len += 1;
+    }
+}
+
+struct Depot {
+    free: *mut FreeObject,
+    free_objects: usize,
+    slabs: usize,
+    total_objects: usize,
+}
+
+// 链表节点只在持有 depot 锁时访问
+unsafe impl Send for Depot {}
+unsafe impl Send for Magazine {}
+
+impl Depot {
+    const fn new() -> Self {
+        Self { free: ptr::null_mut(), free_objects: 0, slabs: 0, total_objects: 0 }
+    }
+
+    fn push(&mut self, object: *mut u8) {
+        let node = object as *mut FreeObject;
+        unsafe { (*node).next = self.free };
+        self.free = node;
+        self.free_objects += 1;
+    }
+
+    fn pop(&mut self) -> Option<*mut u8> {
+        let node = self.free;
+        if node.is_null() {
+            return None;
+        }
+        self.free = unsafe { (*node).next };
+        self.free_objects -= 1;
+        Some(node as *mut u8)
+    }
+}
+
+pub struct SlabCache {
+    name: &'static str,
+    // 对齐后的对象大小
+    size: usize,
+    align: usize,
+    depot: SpinLock<Depot>,
+    magazines: [SpinLock<Magazine>; MAX_HARTS],
+    allocs: AtomicUsize,
+    frees: AtomicUsize,
+    registered: AtomicBool,
+}
+
+impl SlabCache {
+    pub const fn new(name: &'static str, size: usize, align: usize) -> Self {
+        let align = if align > align_of::<FreeObject>() { align } else { align_of::<FreeObject>() };
+        let size = if size > size_of::<FreeObject>() { size } else { size_of::<FreeObject>() };
+        let size = size.next_multiple_of(align);
+        assert!(size <= PAGE_SIZE << (MAX_ORDER - 1), "slab object too large");
+        assert!(align <= PAGE_SIZE, "slab object alignment exceeds a page");
+        // 各个缓存的锁在同一处创建, 以缓存名区分锁类, 否则 lockdep 会把嵌套的两个缓存当作同一类
+        let mut magazines = [const { SpinLock::new(Magazine::new()) }; MAX_HARTS];
+        let mut index = 0;
+        while index < MAX_HARTS {
+            magazines[index] = SpinLock::new_with_class(Magazine::new(), name);
+            index += 1;
+        }
+        Self {
+            name,
+            size,
+            align,
+            depot: SpinLock::new_with_class(Depot::new(), name),
+            magazines,
+            allocs: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn object_size(&self) -> usize {
+        self.size
+    }
+
+    // 能容纳至少 MIN_OBJECTS_PER_SLAB 个对象的最小阶
+    fn slab_order(&self) -> usize {
+        (0..MAX_ORDER)
+            .find(|&order| (PAGE_SIZE << order) / self.size >= MIN_OBJECTS_PER_SLAB)
+            .unwrap_or(MAX_ORDER - 1)
+    }
+
+    // 申请一个新的 slab, 把其中的对象全部放入 depot
+    fn grow(&self, depot: &mut Depot) -> bool {
+        let order = self.slab_order();
+        let Some(pa) = frame::alloc_frames(order) else {
+            return false;
+        };
+        let base = phys_to_virt(pa);
+        let count = (PAGE_SIZE << order) / self.size;
+        for i in (0..count).rev() {
+            let object = (base + i * self.size) as *mut u8;
+            poison(object, self.size);
+            depot.push(object);
+        }
+        depot.slabs += 1;
+        depot.total_objects += count;
+        true
+    }
+
+    // 从 depot 取半个 magazine 的对象, depot 不足时先扩容
+    fn refill(&self, magazine: &mut Magazine) {
+        let mut depot = self.depot.lock();
+        while magazine.len < MAGAZINE_SIZE / 2 {
+            match depot.pop() {
+                Some(object) => magazine.push(object),
+                None if self.grow(&mut depot) => {}
+                None => break,
+            }
+        }
+    }
+
+    // 把半个 magazine 的对象还给 depot
+    fn flush(&self, magazine: &mut Magazine) {
+        let mut depot = self.depot.lock();
+        while magazine.len > MAGAZINE_SIZE / 2 {
+            let object = magazine.pop().unwrap();
+            depot.push(object);
+        }
+    }
+
+    pub fn alloc(&'static self) -> Option<NonNull<u8>> {
+        self.register();
+        let object = match self.magazines.get(hart::current_id()) {
+            Some(magazine) => {
+                let mut magazine = magazine.lock();
+                if magazine.len == 0 {
+                    self.refill(&mut magazine);
+                }
+                magazine.pop()
+            }
+            // 超出 MAX_HARTS 的 hart 直接使用 depot
+            None => {
+                let mut depot = self.depot.lock();
+                depot.pop().or_else(|| if self.grow(&mut depot) { depot.pop() } else { None })
+            }
+        }?;
+
+        check_poison(self.name, object, self.size);
+        self.allocs.fetch_add(1, Ordering::Relaxed);
+        NonNull::new(object)
+    }
+
+    /*
+     ptr 必须来自同一个 cache 的 alloc, 且之后不再被使用
+    */
+    pub unsafe fn free(&self, object: NonNull<u8>) {
+        let object = object.as_ptr();
+        debug_assert!(
+            (object as usize).is_multiple_of(self.align),
+            "slab {}: bad free {:p}",
+            self.name,
+            object
+        );
+        poison(object, self.size);
+        self.frees.fetch_add(1, Ordering::Relaxed);
+
+        match self.magazines.get(hart::current_id()) {
+            Some(magazine) => {
+                let mut magazine = magazine.lock();
+                if magazine.len == MAGAZINE_SIZE {
+                    self.flush(&mut magazine);
+                }
+                magazine.push(object);
+            }
+            None => self.depot.lock().push(object),
+        }
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        // 加锁顺序与 alloc/free 一致: 先 magazine 后 depot
+        let cached = self.magazines.iter().map(|magazine| magazine.lock().len).sum();
+        let depot = self.depot.lock();
+        let allocs = self.allocs.load(Ordering::Relaxed);
+        let frees = self.frees.load(Ordering::Relaxed);
+        SlabStats {
+            name: self.name,
+            object_size: self.size,
+            slabs: depot.slabs,
+            slab_bytes: depot.slabs * (PAGE_SIZE << self.slab_order()),
+            total_objects: depot.total_objects,
+            in_use: allocs.saturating_sub(frees),
+            cached,
+            allocs,
+            frees,
+        }
+    }
+
+    // 第一次分配时登记到 CACHES, 供统计使用
+    fn register(&'static self) {
+        if !self.registered.swap(true, Ordering::AcqRel) {
+            CACHES.lock().push(self);
+        }
+    }
+}
+
+#[cfg(debug_assertions)]
+fn poison(object: *mut u8, size: usize) {
+    unsafe { ptr::write_bytes(object, POISON_FREE, size) };
+}
+
+#[cfg(not(debug_assertions))]
+fn poison(_object: *mut u8, _size: usize) {}
+
+// 开头的 FreeObject 会被链表指针覆盖, 不参与检查
+#[cfg(debug_assertions)]
+fn check_poison(name: &str, object: *mut u8, size: usize) {
+    let skip = size_of::<FreeObject>();
+    let body = unsafe { core::slice::from_raw_parts(object.add(skip), size - skip) };
+    if let Some(offset) = body.iter().position(|&b| b != POISON_FREE) {
+        panic!(
+            "slab {}: object {:p} modified after free (offset {}, byte 0x{:02x})",
+            name,
+            object,
+            skip + offset,
+            body[offset]
+        );
+    }
+}
+
+#[cfg(not(debug_assertions))]
+fn check_poison(_name: &str, _object: *mut u8, _size: usize) {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub slabs: usize,
+    pub slab_bytes: usize,
+    pub total_objects: usize,
+    pub in_use: usize,
+    // 停留在各 hart magazine 中的空闲对象
+    pub cached: usize,
+    pub allocs: usize,
+    pub frees: usize,
+}
+
+static CACHES: SpinLock<Vec<&'static SlabCache>> = SpinLock::new(Vec::new());
+
+pub fn for_each_cache(mut f: impl FnMut(SlabStats)) {
+    let caches = CACHES.lock().clone();
+    for cache in caches {
+        f(cache.stats());
+    }
+}
+
+pub fn print_stats() {
+    printk!(
+        "{:<16} {:>6} {:>6} {:>8} {:>8} {:>8} {:>10}",
+        "cache",
+        "size",
+        "slabs",
+        "objects",
+        "in use",
+        "cached",
+        "allocs"
+    );
+    for_each_cache(|stats| {
+        printk!(
+            "{:<16} {:>6} {:>6} {:>8} {:>8} {:>8} {:>10}",
+            stats.name,
+            stats.object_size,
+            stats.slabs,
+            stats.total_objects,
+            stats.in_use,
+            stats.cached,
+            stats.allocs
+        );
+    });
+}
+
+/*
+ 类型化的对象缓存:
+
+   static ENDPOINTS: ObjectCache<Endpoint> = ObjectCache::new("endpoint");
+   let ep = ENDPOINTS.alloc(Endpoint::new())?;
+   ...
+   unsafe { ENDPOINTS.free(ep) };
+*/
+pub struct ObjectCache<T> {
+    cache: SlabCache,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Sync for ObjectCache<T> {}
+
+impl<T> ObjectCache<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self { cache: SlabCache::new(name, size_of::<T>(), align_of::<T>()), _marker: PhantomData }
+    }
+
+    pub fn alloc(&'static self, value: T) -> Option<NonNull<T>> {
+        let object = self.cache.alloc()?.cast::<T>();
+        unsafe { object.write(value) };
+        Some(object)
+    }
+
+    // 析构对象并归还, object 必须来自本 cache 且之后不再被使用
+    pub unsafe fn free(&self, object: NonNull<T>) {
+        unsafe {
+            object.drop_in_place();
+            self.cache.free(object.cast());
+        }
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        self.cache.stats()
+    }
+}
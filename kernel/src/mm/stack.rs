@@ -0,0 +1,118 @@
+use core::fmt;
+use core::sync::atomic::{Ordering, fence};
+
+use super::frame;
+use super::{PAGE_SIZE, layout, phys_to_virt};
+use crate::hart::MAX_HARTS;
+use crate::percpu;
+
+/*
+ 内核栈
+
+ 内核栈窗口中每个 hart id 占一个 KERNEL_STACK_SLOT, 栈向下增长:
+
+   +--------------------+--------------------+--------------------+---
+   | 保护页 (不映射)       | hart 0 的栈          | 保护页 (不映射)       | hart 1 ...
+   +--------------------+--------------------+--------------------+---
+   窗口基址             KERNEL_STACK_GUARD    KERNEL_STACK_SLOT
+
+ 窗口的末级页表 (boot_stack_l0) 由 boot.S 静态分配, 覆盖全部 MAX_HARTS 个栈槽,
+ 引导 hart 的栈由 boot.S 映射, 其余 hart 的栈在启动它们之前从页帧分配器申请,
+ 因此只有实际存在的 hart 占用物理内存
+
+ 每个 hart 另有一页应急栈, 记录在 PerCpu 中, 内核栈溢出时由 trap/entry.S 切换过去
+
+ Also see:
+ Glenda/kernel/src/boot.S
+ Glenda/kernel/src/trap/entry.S
+*/
+
+// 与 boot.S 中的同名常量一致
+pub const KERNEL_STACK_SIZE: usize = 16 * 1024;
+pub const KERNEL_STACK_GUARD: usize = 16 * 1024;
+pub const KERNEL_STACK_SLOT: usize = KERNEL_STACK_SIZE + KERNEL_STACK_GUARD;
+const _: () = assert!(MAX_HARTS * KERNEL_STACK_SLOT <= 1 << 30, "stack window is 1 GiB");
+
+const STACK_ORDER: usize = (KERNEL_STACK_SIZE / PAGE_SIZE).trailing_zeros() as usize;
+const PTE_STACK: usize = 0xe7; // V | R | W | G | A | D
+
+unsafe extern "C" {
+    // 只声明用到的部分, 实际大小按 2 MiB 向上取整
+    static mut boot_stack_l0: [usize; MAX_HARTS * KERNEL_STACK_SLOT / PAGE_SIZE];
+}
+
+#[derive(Debug)]
+pub enum StackError {
+    // hart id 超出 MAX_HARTS, 没有对应的栈槽
+    NoSlot(usize),
+    OutOfMemory,
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StackError::NoSlot(hartid) => write!(
+                f,
+                "hart id {} exceeds the configured limit of {} (rebuild with GLENDA_MAX_HARTS)",
+                hartid, MAX_HARTS
+            ),
+            StackError::OutOfMemory => write!(f, "out of memory for its kernel stack"),
+        }
+    }
+}
+
+// hart 的内核栈 [底, 顶)
+pub fn kernel_stack(hartid: usize) -> (usize, usize) {
+    let top = layout().stack_base + (hartid + 1) * KERNEL_STACK_SLOT;
+    (top - KERNEL_STACK_SIZE, top)
+}
+
+// va 落在哪个 hart 的栈保护页中
+pub fn stack_guard_owner(va: usize) -> Option<usize> {
+    let offset = va.checked_sub(layout().stack_base)?;
+    let hartid = offset / KERNEL_STACK_SLOT;
+    (hartid < MAX_HARTS && offset % KERNEL_STACK_SLOT < KERNEL_STACK_GUARD).then_some(hartid)
+}
+
+// 栈槽中第一个栈页对应的末级页表项
+fn stack_ptes(hartid: usize) -> *mut usize {
+    let index = (hartid * KERNEL_STACK_SLOT + KERNEL_STACK_GUARD) / PAGE_SIZE;
+    unsafe { (&raw mut boot_stack_l0).cast::<usize>().add(index) }
+}
+
+fn alloc_emergency_stack(hartid: usize) -> Result<(), StackError> {
+    let percpu = percpu::of(hartid);
+    if percpu.emergency_stack_top.load(Ordering::Acquire) != 0 {
+        return Ok(());
+    }
+    let pa = frame::alloc_frames(0).ok_or(StackError::OutOfMemory)?;
+    percpu.emergency_stack_top.store(phys_to_virt(pa) + PAGE_SIZE, Ordering::Release);
+    Ok(())
+}
+
+/*
+ 为即将启动的 hart 准备内核栈与应急栈, 已经准备过的 hart 直接返回
+ 映射完成后, 已经在 boot.S 中等待的 hart 会自行继续启动
+
+ 新映射的页表项在目标 hart 打开分页时才会被访问, 不需要刷新 TLB
+*/
+pub fn prepare(hartid: usize) -> Result<(), StackError> {
+    if hartid >= MAX_HARTS {
+        return Err(StackError::NoSlot(hartid));
+    }
+    alloc_emergency_stack(hartid)?;
+
+    let ptes = stack_ptes(hartid);
+    if unsafe { ptes.read_volatile() } != 0 {
+        return Ok(());
+    }
+    let pa = frame::alloc_frames(STACK_ORDER).ok_or(StackError::OutOfMemory)?;
+    let pte = |page: usize| ((pa + page * PAGE_SIZE) >> 12) << 10 | PTE_STACK;
+    for page in 1..KERNEL_STACK_SIZE / PAGE_SIZE {
+        unsafe { ptes.add(page).write_volatile(pte(page)) };
+    }
+    // 在抽签中落选的 hart 轮询第一个页表项, 因此它必须最后写入
+    fence(Ordering::Release);
+    unsafe { ptes.write_volatile(pte(0)) };
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use riscv::asm::wfi;
+use riscv::register::sie;
+
+use crate::backtrace::Backtrace;
+use crate::hart::{self, MAX_HARTS};
+use crate::ipi;
+use crate::ksyms::Sym;
+use crate::printk;
+use crate::printk::{ANSI_RED, ANSI_RESET};
+use crate::timer;
+use crate::trace;
+use crate::trap::{self, TrapFrame};
+use crate::watchdog;
+
+/*
+ panic 处理
+
+ 第一个 panic 的 hart 负责整份报告:
+   1. 把 printk 切到 panic 模式, 之后的输出不会因为 PRINTK_LOCK 而卡住 (见 printk/mod.rs)
+   2. 打印 PanicInfo 与栈回溯; 未处理的陷入也经由 panic 到这里
+   3. 用 IPI 停下其它 hart, 每个 hart 在软件中断中打印自己被打断的位置和栈回溯后停机
+   4. 等待至多 STOP_TIMEOUT_MS, 仍未停下的 hart (多半关着中断卡住了)
+      用 watchdog 记录的最近一次时钟中断位置代替
+   5. 打开了 trace feature 时打印跟踪缓冲区
+
+ 其它 hart 随后 panic 时只打印自己的 PanicInfo 然后停机;
+ panic 处理本身又 panic 时直接写 UART, 不再经过 printk
+
+ Also see:
+ Glenda/kernel/src/backtrace.rs
+ Glenda/kernel/src/ipi.rs
+ Glenda/kernel/src/watchdog.rs
+*/
+const STOP_TIMEOUT_MS: usize = 1000;
+const NO_HART: usize = usize::MAX;
+
+static PANIC_HART: AtomicUsize = AtomicUsize::new(NO_HART);
+static STOPPED: [AtomicBool; MAX_HARTS] = [const { AtomicBool::new(false) }; MAX_HARTS];
+
+pub fn in_progress() -> bool {
+    PANIC_HART.load(Ordering::Acquire) != NO_HART
+}
+
+pub fn handle(info: &PanicInfo) -> ! {
+    trap::local_irq_save();
+    printk::enter_panic_mode();
+    let me = hart::current_id();
+    match PANIC_HART.compare_exchange(NO_HART, me, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => {}
+        Err(owner) if owner == me => {
+            driver_uart::print!("\nrecursive panic on hart {}: {}\n", me, info);
+            halt();
+        }
+        Err(owner) => {
+            printk!(
+                "{}PANIC{} on hart {} while hart {} is panicking: {}",
+                ANSI_RED,
+                ANSI_RESET,
+                me,
+                owner,
+                info
+            );
+            STOPPED[me].store(true, Ordering::Release);
+            halt();
+        }
+    }
+
+    printk!("{}PANIC{} on hart {}: {}", ANSI_RED, ANSI_RESET, me, info);
+    printk!("{}", Backtrace::capture());
+    stop_other_harts(me);
+    trace::dump();
+    halt();
+}
+
+fn others(me: usize) -> impl Iterator<Item = usize> {
+    (0..hart::cpu_count())
+        .filter_map(hart::hart_id)
+        .filter(move |&hartid| hartid != me && hartid < MAX_HARTS)
+}
+
+fn stop_other_harts(me: usize) {
+    let targets = ipi::send_others(ipi::IPI_STOP);
+    if targets > 0 {
+        let start = timer::now();
+        let timeout = timer::ms_to_ticks(STOP_TIMEOUT_MS);
+        while others(me).any(|hartid| ipi::is_online(hartid) && !is_stopped(hartid))
+            && timer::now().wrapping_sub(start) < timeout
+        {
+            core::hint::spin_loop();
+        }
+    }
+
+    for hartid in others(me).filter(|&hartid| !is_stopped(hartid)) {
+        match watchdog::last_pc(hartid) {
+            Some((pc, ago)) => printk!(
+                "hart {} did not stop, last timer interrupt at pc 0x{:x} ({}, {} ms ago)",
+                hartid,
+                pc,
+                Sym::pc(pc),
+                ago
+            ),
+            None => printk!("hart {} was not running", hartid),
+        }
+    }
+    printk!("{}all harts halted{}", ANSI_RED, ANSI_RESET);
+}
+
+fn is_stopped(hartid: usize) -> bool {
+    STOPPED[hartid].load(Ordering::Acquire)
+}
+
+// 收到 IPI_STOP 的 hart 报告被打断的位置后停机
+pub fn stop_this_hart(frame: &TrapFrame) -> ! {
+    let me = hart::current_id();
+    printk!("hart {} stopped at pc 0x{:x} ({})", me, frame.sepc, Sym::pc(frame.sepc));
+    printk!("{}", Backtrace::capture_trap(frame.sepc));
+    STOPPED[me].store(true, Ordering::Release);
+    halt();
+}
+
+// 关闭中断并清除中断使能, 否则挂起的中断会让 wfi 立即返回
+pub fn halt() -> ! {
+    trap::local_irq_save();
+    unsafe {
+        sie::clear_stimer();
+        sie::clear_ssoft();
+    }
+    loop {
+        wfi();
+    }
+}
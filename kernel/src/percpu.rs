@@ -0,0 +1,119 @@
+#![allow(dead_code)]
+
+use core::arch::asm;
+use core::cell::Cell;
+use core::mem::{offset_of, size_of};
+use core::ptr;
+use core::sync::atomic::AtomicUsize;
+
+use crate::hart::MAX_HARTS;
+
+/*
+ 每个 hart 的私有数据块 (per-CPU)
+
+ 数据块按 hart id 静态排列在 PERCPU 中, boot.S 在进入 glenda_main 之前
+ 把本 hart 数据块的地址写入 tp, 并填好 hartid; 编译器不会分配 tp
+ 之后引入用户态时, 在用户态运行期间由 sscratch 保存该地址, 陷入时再换回 tp
+
+ 除 emergency_stack_top 和 rcu_qs 外, 各字段只由所属 hart 自己访问,
+ 因此用 Cell 而不是原子变量; 所有字段都通过 percpu! 访问:
+
+   percpu!(irq_depth).get()
+   percpu!(cpu).get()
+   percpu::of(hartid).rcu_qs.load(Ordering::Acquire)   // 其它 hart 只能访问原子字段
+
+ Also see:
+ Glenda/kernel/src/boot.S
+ Glenda/kernel/src/trap/entry.S
+*/
+
+// 与 boot.S、trap/entry.S 中的 PERCPU_* 一致
+pub const PERCPU_SIZE: usize = 128;
+
+#[repr(C, align(64))]
+pub struct PerCpu {
+    pub(crate) hartid: Cell<usize>,
+    // 由引导 hart 在启动本 hart 之前写入, 见 mm/stack.rs
+    pub(crate) emergency_stack_top: AtomicUsize,
+    // kernel_trap_entry 检查内核栈期间暂存 t0, 见 trap/entry.S
+    pub(crate) trap_scratch: Cell<usize>,
+    // 逻辑 CPU 编号, 不在设备树中的 hart 为 None
+    pub(crate) cpu: Cell<Option<usize>>,
+    // 中断 (陷入) 嵌套深度
+    pub(crate) irq_depth: Cell<usize>,
+    // 线程与调度器尚未实现, 先以不透明指针保存
+    pub(crate) current_thread: Cell<*mut ()>,
+    pub(crate) runqueue: Cell<*mut ()>,
+    // RCU 读端临界区嵌套深度
+    pub(crate) rcu_nesting: Cell<usize>,
+    // 最近一次经过静止状态时看到的宽限期序号, usize::MAX 表示尚未上线, 见 rcu.rs
+    pub(crate) rcu_qs: AtomicUsize,
+}
+
+const _: () = assert!(size_of::<PerCpu>() == PERCPU_SIZE);
+const _: () = assert!(offset_of!(PerCpu, hartid) == 0);
+const _: () = assert!(offset_of!(PerCpu, emergency_stack_top) == 8);
+const _: () = assert!(offset_of!(PerCpu, trap_scratch) == 16);
+
+// 每个数据块只被所属 hart 以非原子方式访问
+unsafe impl Sync for PerCpu {}
+
+#[unsafe(no_mangle)]
+static PERCPU: [PerCpu; MAX_HARTS] = [const { PerCpu::new() }; MAX_HARTS];
+
+#[macro_export]
+macro_rules! percpu {
+    ($field:ident) => {
+        &$crate::percpu::this_cpu().$field
+    };
+}
+
+impl PerCpu {
+    const fn new() -> Self {
+        Self {
+            hartid: Cell::new(0),
+            emergency_stack_top: AtomicUsize::new(0),
+            trap_scratch: Cell::new(0),
+            cpu: Cell::new(None),
+            irq_depth: Cell::new(0),
+            current_thread: Cell::new(ptr::null_mut()),
+            runqueue: Cell::new(ptr::null_mut()),
+            rcu_nesting: Cell::new(0),
+            rcu_qs: AtomicUsize::new(usize::MAX),
+        }
+    }
+}
+
+// 当前 hart 的数据块
+pub fn this_cpu() -> &'static PerCpu {
+    let block: *const PerCpu;
+    unsafe {
+        asm!("mv {}, tp", out(reg) block, options(nomem, nostack, preserves_flags));
+        &*block
+    }
+}
+
+// 其它 hart 的数据块, 只应访问其中的原子字段
+pub fn of(hartid: usize) -> &'static PerCpu {
+    &PERCPU[hartid]
+}
+
+// 在本 hart 上调用一次, 此时设备树已经解析完毕
+pub fn init(cpu: Option<usize>) {
+    this_cpu().cpu.set(cpu);
+}
+
+pub fn irq_enter() {
+    let depth = percpu!(irq_depth);
+    depth.set(depth.get() + 1);
+}
+
+pub fn irq_exit() {
+    let depth = percpu!(irq_depth);
+    debug_assert!(depth.get() > 0, "irq_exit without irq_enter");
+    depth.set(depth.get() - 1);
+}
+
+pub fn in_interrupt() -> bool {
+    percpu!(irq_depth).get() > 0
+}
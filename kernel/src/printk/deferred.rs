@@ -0,0 +1,288 @@
+use core::fmt::{self, Write};
+use core::panic::Location;
+
+use crate::hart;
+use crate::ksyms::Sym;
+use crate::mm::stack::StackError;
+use crate::timer;
+
+use super::Level;
+
+/*
+ 延迟格式化的分级日志 (deferred-log feature), 思路同 defmt
+
+ pr_err!/pr_warn!/pr_info!/pr_debug! 不在内核中格式化, 而是输出一个二进制帧:
+ 格式串的编号加上原始参数, 由 `cargo xtask decode-log` 对照内核 ELF 还原成文本.
+ 格式串连同级别、模块名存放在不加载的 .glenda_log 段中, 不占内核映像的内存
+
+ 段中的每条记录:
+   id: u32 | level: u8 | module 长度: u16 | module | 格式串长度: u16 | 格式串
+ id 是文件名、行号与格式串的 FNV-1a 哈希, 编译期算出
+
+ 帧 (多字节整数均为小端):
+   0xff | 负载长度: u16 | id: u32 | 启动以来的微秒数: varint | hartid: varint | 参数...
+ 0xff 不会出现在 UTF-8 文本中, 解码器据此从普通 printk 输出中找出帧.
+ 每个参数以类型标记开头, 见 TAG_*; 超出 MAX_FRAME 的参数被丢弃, 解码器显示为 <?>
+
+ 限制: 只支持按位置的参数, 不支持 {name} 这类内联捕获;
+ 参数类型需要实现 Encode, 只有 Display/Debug 的内核类型在这里预先格式化成字符串.
+ printk! 与 panic 信息仍然使用 core::fmt
+
+ Also see:
+ Glenda/kernel/src/linker.ld
+ Glenda/xtask/src/defmt.rs
+*/
+pub const FRAME_START: u8 = 0xff;
+const MAX_FRAME: usize = 256;
+
+const TAG_UNSIGNED: u8 = 0;
+const TAG_SIGNED: u8 = 1;
+const TAG_BOOL: u8 = 2;
+const TAG_CHAR: u8 = 3;
+const TAG_STR: u8 = 4;
+const TAG_PTR: u8 = 5;
+// 内核中已经格式化好的文本, 解码器忽略格式说明原样输出
+const TAG_PREFORMATTED: u8 = 6;
+// file: str, line: varint, column: varint
+const TAG_LOCATION: u8 = 7;
+
+pub const fn id(file: &str, line: u32, format: &str) -> u32 {
+    const FNV_OFFSET: u32 = 0x811c_9dc5;
+    const FNV_PRIME: u32 = 0x0100_0193;
+    let mut hash = FNV_OFFSET;
+    let line = line.to_le_bytes();
+    let parts: [&[u8]; 3] = [file.as_bytes(), &line, format.as_bytes()];
+    let mut i = 0;
+    while i < parts.len() {
+        let mut j = 0;
+        while j < parts[i].len() {
+            hash = (hash ^ parts[i][j] as u32).wrapping_mul(FNV_PRIME);
+            j += 1;
+        }
+        i += 1;
+    }
+    hash
+}
+
+pub const fn record_len(module: &str, format: &str) -> usize {
+    4 + 1 + 2 + module.len() + 2 + format.len()
+}
+
+pub const fn record<const N: usize>(
+    file: &str,
+    line: u32,
+    level: Level,
+    module: &str,
+    format: &str,
+) -> [u8; N] {
+    let mut record = [0; N];
+    let id = id(file, line, format).to_le_bytes();
+    let mut pos = 0;
+    while pos < 4 {
+        record[pos] = id[pos];
+        pos += 1;
+    }
+    record[pos] = level as u8;
+    pos += 1;
+    let parts = [module.as_bytes(), format.as_bytes()];
+    let mut i = 0;
+    while i < parts.len() {
+        let len = (parts[i].len() as u16).to_le_bytes();
+        record[pos] = len[0];
+        record[pos + 1] = len[1];
+        pos += 2;
+        let mut j = 0;
+        while j < parts[i].len() {
+            record[pos] = parts[i][j];
+            pos += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+    record
+}
+
+pub struct Frame {
+    buf: [u8; MAX_FRAME],
+    len: usize,
+    // 有参数放不下之后不再写入, 避免后面的参数错位
+    full: bool,
+}
+
+impl Frame {
+    #[inline(never)]
+    pub fn new(id: u32) -> Self {
+        let mut frame = Self { buf: [0; MAX_FRAME], len: 3, full: false };
+        frame.buf[0] = FRAME_START;
+        frame.bytes(&id.to_le_bytes());
+        let now = timer::now() as u64;
+        let frequency = timer::frequency() as u64;
+        frame.varint(now / frequency * 1_000_000 + now % frequency * 1_000_000 / frequency);
+        frame.varint(hart::current_id() as u64);
+        frame
+    }
+
+    // 一个参数整体写入或者整体丢弃
+    #[inline(never)]
+    fn arg(&mut self, tag: u8, f: impl FnOnce(&mut Self)) {
+        if self.full {
+            return;
+        }
+        let start = self.len;
+        self.bytes(&[tag]);
+        f(self);
+        if self.full {
+            self.len = start;
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        let end = self.len + bytes.len();
+        if end > MAX_FRAME {
+            self.full = true;
+            return;
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+    }
+
+    #[inline(never)]
+    fn varint(&mut self, mut value: u64) {
+        loop {
+            let byte = value as u8 & 0x7f;
+            value >>= 7;
+            if value == 0 {
+                return self.bytes(&[byte]);
+            }
+            self.bytes(&[byte | 0x80]);
+        }
+    }
+
+    fn str(&mut self, s: &str) {
+        let len = s.len().min(u16::MAX as usize);
+        self.bytes(&(len as u16).to_le_bytes());
+        self.bytes(&s.as_bytes()[..len]);
+    }
+
+    // 长度先占位, 写完再回填
+    fn preformatted(&mut self, args: fmt::Arguments) {
+        self.arg(TAG_PREFORMATTED, |frame| {
+            let start = frame.len;
+            frame.bytes(&[0, 0]);
+            if frame.write_fmt(args).is_err() {
+                frame.full = true;
+            }
+            if !frame.full {
+                let len = (frame.len - start - 2) as u16;
+                frame.buf[start..start + 2].copy_from_slice(&len.to_le_bytes());
+            }
+        });
+    }
+
+    #[inline(never)]
+    pub fn emit(mut self) {
+        let len = (self.len - 3) as u16;
+        self.buf[1..3].copy_from_slice(&len.to_le_bytes());
+        super::_printk_bytes(&self.buf[..self.len]);
+    }
+}
+
+impl Write for Frame {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.bytes(s.as_bytes());
+        if self.full { Err(fmt::Error) } else { Ok(()) }
+    }
+}
+
+pub trait Encode {
+    fn encode(&self, frame: &mut Frame);
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, frame: &mut Frame) {
+        (**self).encode(frame)
+    }
+}
+
+macro_rules! encode_unsigned {
+    ($($ty:ty),*) => {$(
+        impl Encode for $ty {
+            fn encode(&self, frame: &mut Frame) {
+                frame.arg(TAG_UNSIGNED, |frame| frame.varint(*self as u64));
+            }
+        }
+    )*};
+}
+encode_unsigned!(u8, u16, u32, u64, usize);
+
+// zigzag 编码, 小的负数也只占一两个字节
+macro_rules! encode_signed {
+    ($($ty:ty),*) => {$(
+        impl Encode for $ty {
+            fn encode(&self, frame: &mut Frame) {
+                let value = *self as i64;
+                frame.arg(TAG_SIGNED, |frame| frame.varint(((value << 1) ^ (value >> 63)) as u64));
+            }
+        }
+    )*};
+}
+encode_signed!(i8, i16, i32, i64, isize);
+
+impl Encode for bool {
+    fn encode(&self, frame: &mut Frame) {
+        frame.arg(TAG_BOOL, |frame| frame.bytes(&[*self as u8]));
+    }
+}
+
+impl Encode for char {
+    fn encode(&self, frame: &mut Frame) {
+        frame.arg(TAG_CHAR, |frame| frame.varint(*self as u64));
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, frame: &mut Frame) {
+        frame.arg(TAG_STR, |frame| frame.str(self));
+    }
+}
+
+impl<T: ?Sized> Encode for *const T {
+    fn encode(&self, frame: &mut Frame) {
+        frame.arg(TAG_PTR, |frame| frame.varint(self.cast::<()>() as usize as u64));
+    }
+}
+
+impl<T: ?Sized> Encode for *mut T {
+    fn encode(&self, frame: &mut Frame) {
+        self.cast_const().encode(frame)
+    }
+}
+
+impl Encode for Location<'_> {
+    fn encode(&self, frame: &mut Frame) {
+        frame.arg(TAG_LOCATION, |frame| {
+            frame.str(self.file());
+            frame.varint(self.line() as u64);
+            frame.varint(self.column() as u64);
+        });
+    }
+}
+
+// 以下类型只有 Display/Debug, 在内核中格式化
+impl Encode for StackError {
+    fn encode(&self, frame: &mut Frame) {
+        frame.preformatted(format_args!("{}", self));
+    }
+}
+
+impl Encode for Sym {
+    fn encode(&self, frame: &mut Frame) {
+        frame.preformatted(format_args!("{}", self));
+    }
+}
+
+impl Encode for fdt::FdtError {
+    fn encode(&self, frame: &mut Frame) {
+        frame.preformatted(format_args!("{:?}", self));
+    }
+}
@@ -0,0 +1,84 @@
+use core::cell::UnsafeCell;
+
+/*
+ 内核日志环形缓冲区 (dmesg)
+
+ printk 的全部输出按字节顺序写入, 写满之后覆盖最旧的内容
+ 位置用自启动以来写入的总字节数表示, 只增不减:
+ 缓冲区里保存的是 [head - LOG_BUF_SIZE, head) 这一段, 读者记住自己读到的位置,
+ 落后太多时跳到最旧的位置, 中间的内容丢失
+
+ 只在持有 PRINTK_LOCK 时访问, 见 printk/mod.rs
+*/
+pub const LOG_BUF_SIZE: usize = 64 * 1024;
+
+// 全零, 位于 .bss, 不增加内核映像的大小
+struct Storage(UnsafeCell<[u8; LOG_BUF_SIZE]>);
+
+unsafe impl Sync for Storage {}
+
+static STORAGE: Storage = Storage(UnsafeCell::new([0; LOG_BUF_SIZE]));
+
+pub struct LogBuf {
+    head: usize,
+}
+
+impl LogBuf {
+    pub const fn new() -> Self {
+        Self { head: 0 }
+    }
+
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    pub fn oldest(&self) -> usize {
+        self.head.saturating_sub(LOG_BUF_SIZE)
+    }
+
+    pub fn append(&mut self, mut bytes: &[u8]) {
+        // 超过容量时只有最后 LOG_BUF_SIZE 字节会留下
+        if bytes.len() > LOG_BUF_SIZE {
+            self.head += bytes.len() - LOG_BUF_SIZE;
+            bytes = &bytes[bytes.len() - LOG_BUF_SIZE..];
+        }
+        let buf = unsafe { &mut *STORAGE.0.get() };
+        let start = self.head % LOG_BUF_SIZE;
+        let first = bytes.len().min(LOG_BUF_SIZE - start);
+        buf[start..start + first].copy_from_slice(&bytes[..first]);
+        buf[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+        self.head += bytes.len();
+    }
+
+    /*
+     从 *pos 开始读到 out 中, 返回读到的字节数并推进 *pos
+     *pos 早于最旧的内容时先跳到最旧的位置
+    */
+    pub fn read(&self, pos: &mut usize, out: &mut [u8]) -> usize {
+        *pos = (*pos).clamp(self.oldest(), self.head);
+        let buf = unsafe { &*STORAGE.0.get() };
+        let mut copied = 0;
+        while copied < out.len() && *pos < self.head {
+            let start = *pos % LOG_BUF_SIZE;
+            let len = (out.len() - copied).min(self.head - *pos).min(LOG_BUF_SIZE - start);
+            out[copied..copied + len].copy_from_slice(&buf[start..start + len]);
+            copied += len;
+            *pos += len;
+        }
+        copied
+    }
+
+    // 按顺序交出缓冲区中的全部内容, 回绕时分成两段
+    pub fn for_each_chunk(&self, mut f: impl FnMut(&[u8])) {
+        let buf = unsafe { &*STORAGE.0.get() };
+        let start = self.oldest() % LOG_BUF_SIZE;
+        let len = self.head - self.oldest();
+        let first = len.min(LOG_BUF_SIZE - start);
+        if first > 0 {
+            f(&buf[start..start + first]);
+        }
+        if len > first {
+            f(&buf[..len - first]);
+        }
+    }
+}
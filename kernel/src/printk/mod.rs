@@ -0,0 +1,352 @@
+#![allow(dead_code)]
+
+#[cfg(feature = "deferred-log")]
+pub mod deferred;
+pub mod logbuf;
+
+use core::fmt::{self, Write};
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::hart;
+use crate::lock::{SpinLock, SpinLockGuard};
+use crate::timer;
+use crate::trap;
+
+use logbuf::LogBuf;
+
+/*
+ printk 的输出先写入日志缓冲区 (见 logbuf.rs), 再交给已注册的控制台
+ 从 glenda_main 的第一条指令开始就可以调用, 此时还没有控制台, 输出只进入缓冲区;
+ 控制台注册时先重放缓冲区中已有的内容, 因此早期输出不会丢失
+
+ 缓冲区之后可以通过 read_log() 读取, 供内核监视器或用户态 syslog 服务使用
+
+ panic 之后改走 with_printk_emergency(): 限时等待 PRINTK_LOCK, 拿不到就不加锁直接写.
+ 此时锁的持有者可能就是本 hart (在 printk 中 panic), 或者已经被停下的其它 hart,
+ 它们都不会再释放锁; 与仍在运行的持有者同时写只会让输出交错, 不会死锁
+
+ Also see:
+ Glenda/kernel/src/printk/logbuf.rs
+*/
+const MAX_CONSOLES: usize = 4;
+// panic 时等待 PRINTK_LOCK 的上限
+const PANIC_LOCK_TIMEOUT_MS: usize = 100;
+
+#[derive(Clone, Copy)]
+pub struct Console {
+    pub name: &'static str,
+    // 原样输出字节, 换行的转换由控制台自己负责
+    pub write: fn(&[u8]),
+}
+
+struct Printk {
+    log: LogBuf,
+    consoles: [Option<Console>; MAX_CONSOLES],
+}
+
+impl Printk {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.log.append(bytes);
+        for console in self.consoles.iter().flatten() {
+            (console.write)(bytes);
+        }
+    }
+}
+
+impl Write for Printk {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+// 陷入处理中也可能输出, 持锁期间关闭中断
+static PRINTK_LOCK: SpinLock<Printk> =
+    SpinLock::new(Printk { log: LogBuf::new(), consoles: [None; MAX_CONSOLES] });
+static PANIC_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn _printk(args: fmt::Arguments) {
+    with_printk(|printk| {
+        let _ = printk.write_fmt(args);
+    });
+}
+
+// 原样输出字节, 供 deferred-log 的二进制帧使用
+pub fn _printk_bytes(bytes: &[u8]) {
+    with_printk(|printk| printk.write_bytes(bytes));
+}
+
+fn with_printk(f: impl FnOnce(&mut Printk)) {
+    if PANIC_MODE.load(Ordering::Relaxed) {
+        return with_printk_emergency(f);
+    }
+    f(&mut PRINTK_LOCK.lock_irqsave());
+}
+
+// 由 panic 处理调用, 之后所有输出都走 with_printk_emergency()
+pub fn enter_panic_mode() {
+    PANIC_MODE.store(true, Ordering::SeqCst);
+}
+
+fn with_printk_emergency(f: impl FnOnce(&mut Printk)) {
+    let irq_enabled = trap::local_irq_save();
+    match lock_bounded() {
+        Some(mut printk) => f(&mut printk),
+        None => f(unsafe { &mut *PRINTK_LOCK.data_ptr() }),
+    }
+    trap::local_irq_restore(irq_enabled);
+}
+
+fn lock_bounded() -> Option<SpinLockGuard<'static, Printk>> {
+    // 本 hart 持有锁时再等也没有用
+    #[cfg(debug_assertions)]
+    if PRINTK_LOCK.owner() == Some(hart::current_id()) {
+        return None;
+    }
+    let start = timer::now();
+    let timeout = timer::ms_to_ticks(PANIC_LOCK_TIMEOUT_MS);
+    loop {
+        if let Some(guard) = PRINTK_LOCK.try_lock() {
+            return Some(guard);
+        }
+        if timer::now().wrapping_sub(start) > timeout {
+            return None;
+        }
+        spin_loop();
+    }
+}
+
+// 同名的控制台只注册一次; 没有空位时返回 false
+pub fn register_console(console: Console) -> bool {
+    let mut printk = PRINTK_LOCK.lock_irqsave();
+    if printk.consoles.iter().flatten().any(|registered| registered.name == console.name) {
+        return true;
+    }
+    let Some(slot) = printk.consoles.iter_mut().find(|slot| slot.is_none()) else {
+        return false;
+    };
+    *slot = Some(console);
+    // 重放注册之前的输出
+    let lost = printk.log.oldest();
+    if lost > 0 {
+        let mut marker = Marker::new();
+        let _ = writeln!(marker, "[{} bytes of log lost]", lost);
+        (console.write)(marker.as_bytes());
+    }
+    printk.log.for_each_chunk(console.write);
+    true
+}
+
+pub fn unregister_console(name: &str) {
+    let mut printk = PRINTK_LOCK.lock_irqsave();
+    for slot in printk.consoles.iter_mut() {
+        if slot.is_some_and(|console| console.name == name) {
+            *slot = None;
+        }
+    }
+}
+
+/*
+ 从 *pos 开始读取日志, 返回读到的字节数; *pos 从 0 开始即可读到最旧的内容
+
+   let mut pos = 0;
+   let mut buf = [0; 256];
+   while let n @ 1.. = printk::read_log(&mut pos, &mut buf) { ... }
+*/
+pub fn read_log(pos: &mut usize, out: &mut [u8]) -> usize {
+    PRINTK_LOCK.lock_irqsave().log.read(pos, out)
+}
+
+// 自启动以来写入日志的总字节数, 即下一条输出的位置
+pub fn log_head() -> usize {
+    PRINTK_LOCK.lock_irqsave().log.head()
+}
+
+// 格式化丢失提示用的小缓冲区, 不能在持有 PRINTK_LOCK 时分配内存
+struct Marker {
+    buf: [u8; 48],
+    len: usize,
+}
+
+impl Marker {
+    const fn new() -> Self {
+        Self { buf: [0; 48], len: 0 }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for Marker {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[macro_export]
+macro_rules! printk {
+    () => { printk::_printk(format_args!("\n")) };
+    ($fmt:expr) => { printk::_printk(format_args!(concat!($fmt, "\n"))) };
+    ($fmt:expr, $($arg:tt)*) => { printk::_printk(format_args!(concat!($fmt, "\n"), $($arg)*)) };
+}
+pub const ANSI_RESET: &str = "\x1b[0m";
+pub const ANSI_RED: &str = "\x1b[31m";
+pub const ANSI_GREEN: &str = "\x1b[32m";
+pub const ANSI_YELLOW: &str = "\x1b[33m";
+pub const ANSI_BLUE: &str = "\x1b[34m";
+pub const ANSI_MAGENTA: &str = "\x1b[35m";
+pub const ANSI_CYAN: &str = "\x1b[36m";
+pub const ANSI_WHITE: &str = "\x1b[37m";
+
+/*
+ 分级日志
+
+ pr_err!/pr_warn!/pr_info!/pr_debug! 在 printk 之上加上前缀, 并按级别着色:
+
+   [    1.234567] hart 0 mm::heap: message
+
+ 时间取自 time CSR, 模块名取自 module_path!() 去掉 crate 名
+
+ 两道过滤:
+   - 编译期: 构建时的 GLENDA_LOG_LEVEL (error/warn/info/debug, 见 kernel/build.rs),
+     低于它的调用连同参数求值一起被优化掉
+   - 运行期: set_level(), 初始值等于编译期级别, 只能进一步收紧
+
+ 测试输出的 [PASS]/[FAIL] 与 LOGO 这类原样输出仍使用 printk!
+ 打开 deferred-log feature 时改为输出二进制帧, 由主机格式化, 见 deferred.rs
+
+ Also see:
+ Glenda/kernel/src/tests/printk.rs
+*/
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl Level {
+    // build.rs 已经校验过取值, 这里只看首字母
+    const fn parse(s: &str) -> Self {
+        match s.as_bytes()[0] {
+            b'e' => Self::Error,
+            b'w' => Self::Warn,
+            b'i' => Self::Info,
+            _ => Self::Debug,
+        }
+    }
+
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Error,
+            1 => Self::Warn,
+            2 => Self::Info,
+            _ => Self::Debug,
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Self::Error => ANSI_RED,
+            Self::Warn => ANSI_YELLOW,
+            Self::Info => "",
+            Self::Debug => ANSI_CYAN,
+        }
+    }
+}
+
+pub const STATIC_LEVEL: Level = Level::parse(env!("GLENDA_LOG_LEVEL"));
+
+static LEVEL: AtomicU8 = AtomicU8::new(STATIC_LEVEL as u8);
+
+pub fn level() -> Level {
+    Level::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+// 不能放宽到编译期级别之下, 那些调用已经不存在了
+pub fn set_level(level: Level) {
+    LEVEL.store(level.min(STATIC_LEVEL) as u8, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn enabled(level: Level) -> bool {
+    level <= STATIC_LEVEL && level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn _log(level: Level, module: &str, args: fmt::Arguments) {
+    let now = timer::now();
+    let frequency = timer::frequency();
+    let micros = (now % frequency) * 1_000_000 / frequency;
+    let tag = module.split_once("::").map_or(module, |(_, tag)| tag);
+    let (color, reset) = match level.color() {
+        "" => ("", ""),
+        color => (color, ANSI_RESET),
+    };
+    _printk(format_args!(
+        "{}[{:>5}.{:06}] hart {} {}: {}{}\n",
+        color,
+        now / frequency,
+        micros,
+        hart::current_id(),
+        tag,
+        args,
+        reset
+    ));
+}
+
+#[cfg(not(feature = "deferred-log"))]
+#[macro_export]
+macro_rules! pr_log {
+    ($level:expr, $($arg:tt)*) => {{
+        let level = $level;
+        if $crate::printk::enabled(level) {
+            $crate::printk::_log(level, module_path!(), format_args!($($arg)*));
+        }
+    }};
+}
+
+// 级别必须是常量, 与格式串一起记录在 .glenda_log 段中
+#[cfg(feature = "deferred-log")]
+#[macro_export]
+macro_rules! pr_log {
+    ($level:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        use $crate::printk::deferred;
+        const LEVEL: $crate::printk::Level = $level;
+        #[used]
+        #[unsafe(link_section = ".glenda_log")]
+        static RECORD: [u8; deferred::record_len(module_path!(), $fmt)] =
+            deferred::record(file!(), line!(), LEVEL, module_path!(), $fmt);
+        if $crate::printk::enabled(LEVEL) {
+            #[allow(unused_mut)]
+            let mut frame = deferred::Frame::new(deferred::id(file!(), line!(), $fmt));
+            $(deferred::Encode::encode(&$arg, &mut frame);)*
+            frame.emit();
+        }
+    }};
+}
+#[macro_export]
+macro_rules! pr_err {
+    ($($arg:tt)*) => { $crate::pr_log!($crate::printk::Level::Error, $($arg)*) };
+}
+#[macro_export]
+macro_rules! pr_warn {
+    ($($arg:tt)*) => { $crate::pr_log!($crate::printk::Level::Warn, $($arg)*) };
+}
+#[macro_export]
+macro_rules! pr_info {
+    ($($arg:tt)*) => { $crate::pr_log!($crate::printk::Level::Info, $($arg)*) };
+}
+#[macro_export]
+macro_rules! pr_debug {
+    ($($arg:tt)*) => { $crate::pr_log!($crate::printk::Level::Debug, $($arg)*) };
+}
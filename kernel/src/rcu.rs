@@ -0,0 +1,247 @@
+#![allow(dead_code)]
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering, compiler_fence};
+
+use crate::hart::{self, MAX_HARTS};
+use crate::lock::{SpinLock, SpinWait};
+use crate::percpu;
+use crate::trace;
+
+/*
+ RCU (read-copy-update) 延迟回收
+
+ 用于每次 IPC 都要读、却很少修改的结构 (capability 查找表、路由表):
+   - 读者不加锁也不写共享变量, 只增减本 hart 的嵌套计数, 无等待 (wait-free)
+   - 写者用新版本整体替换旧版本, 旧版本要等所有 hart 都经过一次静止状态
+     (quiescent state) 之后才能释放, 此时已经没有读者还能看到它
+
+ 静止状态: 空闲循环, 以及不在读端临界区内时收到的时钟中断;
+ 之后有调度器时调度点也是静止状态. 读端临界区内不能睡眠或主动让出
+
+ 宽限期 (grace period) 用全局序号 GP_SEQ 表示, 写者发布新版本后把它加一;
+ 每个 hart 经过静止状态时把看到的序号记入 PerCpu::rcu_qs,
+ 所有上线的 hart 的 rcu_qs 都不小于 seq 时, 第 seq 个宽限期结束
+
+   static ROUTES: Lazy<RcuCell<Table>> = Lazy::new(|| RcuCell::new(Table::new()));
+
+   // 读者
+   let guard = rcu::read_lock();
+   let route = ROUTES.read(&guard).lookup(dst);
+
+   // 写者之间自行互斥
+   let _lock = ROUTES_WRITER.lock();
+   ROUTES.update(|old| old.with(dst, port));
+
+ 回调在空闲循环和 synchronize() 中执行, 不在中断上下文中, 因此可以释放内存
+
+ Also see:
+ Glenda/kernel/src/percpu.rs
+ Glenda/kernel/src/timer.rs
+ Glenda/kernel/src/tests/rcu.rs
+*/
+const OFFLINE: usize = usize::MAX;
+
+static GP_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+// 按宽限期序号排列的待执行回调
+static CALLBACKS: SpinLock<VecDeque<(usize, Callback)>> = SpinLock::new(VecDeque::new());
+
+// 读端临界区守卫, 不能跨 hart 传递
+pub struct RcuReadGuard {
+    _not_send: PhantomData<*mut ()>,
+}
+
+pub fn read_lock() -> RcuReadGuard {
+    let nesting = percpu!(rcu_nesting);
+    nesting.set(nesting.get() + 1);
+    // 同 Linux 的 barrier(): 临界区内的访问不能被编译器移到计数之前
+    compiler_fence(Ordering::SeqCst);
+    RcuReadGuard { _not_send: PhantomData }
+}
+
+impl Drop for RcuReadGuard {
+    fn drop(&mut self) {
+        let nesting = percpu!(rcu_nesting);
+        debug_assert!(nesting.get() > 0, "rcu read guard without read_lock");
+        // 临界区内的访问不能被编译器移到计数之后, 否则时钟中断可能提前报告静止状态
+        compiler_fence(Ordering::SeqCst);
+        nesting.set(nesting.get() - 1);
+    }
+}
+
+pub fn in_read_section() -> bool {
+    percpu!(rcu_nesting).get() > 0
+}
+
+// 在每个 hart 上调用一次, 之后本 hart 参与宽限期的判断
+pub fn online() {
+    percpu!(rcu_qs).store(GP_SEQ.load(Ordering::Acquire), Ordering::Release);
+}
+
+/*
+ 报告本 hart 经过静止状态, 调用者保证不在读端临界区内
+ Release 保证此前读端临界区内的访问先于写者看到这次报告
+*/
+fn report_qs() {
+    let qs = percpu!(rcu_qs);
+    if qs.load(Ordering::Relaxed) != OFFLINE {
+        qs.store(GP_SEQ.load(Ordering::Acquire), Ordering::Release);
+    }
+}
+
+// 由时钟中断调用, 打断了读端临界区时不算静止状态
+pub fn tick() {
+    if !in_read_section() {
+        report_qs();
+    }
+}
+
+// 由空闲循环调用, 报告静止状态并执行宽限期已经结束的回调
+pub fn quiescent() {
+    debug_assert!(!in_read_section(), "rcu quiescent state inside a read section");
+    report_qs();
+    run_callbacks();
+}
+
+// 开始一个新的宽限期, 返回其序号; 此前发布的新版本对之后进入临界区的读者可见
+fn start_grace_period() -> usize {
+    let seq = GP_SEQ.fetch_add(1, Ordering::AcqRel) + 1;
+    trace!(RcuGracePeriod, seq);
+    seq
+}
+
+// 所有上线的 hart 都已经过的宽限期序号
+fn completed() -> usize {
+    (0..hart::cpu_count())
+        .filter_map(hart::hart_id)
+        .filter(|&hartid| hartid < MAX_HARTS)
+        .map(|hartid| percpu::of(hartid).rcu_qs.load(Ordering::Acquire))
+        .filter(|&qs| qs != OFFLINE)
+        .min()
+        .unwrap_or_else(|| GP_SEQ.load(Ordering::Acquire))
+}
+
+// 当前宽限期结束之后执行 callback
+pub fn call_rcu(callback: impl FnOnce() + Send + 'static) {
+    let callback: Callback = Box::new(callback);
+    let mut callbacks = CALLBACKS.lock();
+    // 在锁内取序号, 队列保持有序
+    let seq = start_grace_period();
+    callbacks.push_back((seq, callback));
+}
+
+// 宽限期结束之后释放 value
+pub fn defer_drop<T: Send + 'static>(value: Box<T>) {
+    call_rcu(move || drop(value));
+}
+
+// 等待当前宽限期结束, 返回时此前被替换的版本已经没有读者
+#[cfg_attr(debug_assertions, track_caller)]
+pub fn synchronize() {
+    debug_assert!(!in_read_section(), "rcu::synchronize inside a read section");
+    let seq = start_grace_period();
+    trace!(begin RcuSynchronize, seq);
+    report_qs();
+    let mut wait = SpinWait::new(&GP_SEQ);
+    while completed() < seq {
+        wait.spin();
+    }
+    trace!(end RcuSynchronize, seq);
+    run_callbacks();
+}
+
+fn run_callbacks() {
+    let done = completed();
+    loop {
+        // 回调可能再次调用 call_rcu, 执行时不持有锁
+        let callback = {
+            let mut callbacks = CALLBACKS.lock();
+            match callbacks.front() {
+                Some(&(seq, _)) if seq <= done => callbacks.pop_front().map(|(_, cb)| cb),
+                _ => None,
+            }
+        };
+        match callback {
+            Some(callback) => callback(),
+            None => break,
+        }
+    }
+}
+
+// 尚未执行的回调数量
+pub fn pending() -> usize {
+    CALLBACKS.lock().len()
+}
+
+/*
+ 受 RCU 保护的指针
+
+ 读者通过 read() 拿到的引用只在守卫的生命周期内有效;
+ 写者用 replace()/update() 发布新版本, 旧版本在宽限期结束后释放.
+ 多个写者之间需要另外加锁, 否则并发的 update() 会丢失其中一个修改
+*/
+pub struct RcuCell<T: Send + Sync + 'static> {
+    ptr: AtomicPtr<T>,
+}
+
+unsafe impl<T: Send + Sync + 'static> Sync for RcuCell<T> {}
+unsafe impl<T: Send + Sync + 'static> Send for RcuCell<T> {}
+
+impl<T: Send + Sync + 'static> RcuCell<T> {
+    pub fn new(value: T) -> Self {
+        Self { ptr: AtomicPtr::new(Box::into_raw(Box::new(value))) }
+    }
+
+    pub fn read<'g>(&self, _guard: &'g RcuReadGuard) -> RcuRef<'g, T> {
+        let value = self.ptr.load(Ordering::Acquire);
+        RcuRef { value: unsafe { &*value } }
+    }
+
+    pub fn replace(&self, value: T) {
+        let old = self.ptr.swap(Box::into_raw(Box::new(value)), Ordering::AcqRel);
+        defer_drop(unsafe { Box::from_raw(old) });
+    }
+
+    // 基于当前版本生成新版本
+    pub fn update(&self, f: impl FnOnce(&T) -> T) {
+        let new = {
+            let guard = read_lock();
+            f(&self.read(&guard))
+        };
+        self.replace(new);
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut **self.ptr.get_mut() }
+    }
+}
+
+impl<T: Send + Sync + 'static> Drop for RcuCell<T> {
+    fn drop(&mut self) {
+        let value = *self.ptr.get_mut();
+        if !value.is_null() {
+            drop(unsafe { Box::from_raw(value) });
+            *self.ptr.get_mut() = ptr::null_mut();
+        }
+    }
+}
+
+// 读端看到的版本, 不能比读端临界区活得更久
+pub struct RcuRef<'g, T> {
+    value: &'g T,
+}
+
+impl<T> Deref for RcuRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
@@ -0,0 +1,64 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use sync::Lazy;
+
+use crate::hart;
+use crate::lock::barrier::Barrier;
+use crate::printk;
+use crate::printk::{ANSI_GREEN, ANSI_RED, ANSI_RESET};
+
+use super::participant;
+
+const ROUNDS: usize = 64;
+
+static BARRIER: Lazy<Barrier> = Lazy::new(|| Barrier::new(hart::cpu_count()));
+static ARRIVALS: AtomicUsize = AtomicUsize::new(0);
+static LEADERS: AtomicUsize = AtomicUsize::new(0);
+// 有 hart 在其它 hart 到达之前就离开了屏障
+static EARLY_EXITS: AtomicUsize = AtomicUsize::new(0);
+
+/*
+ 同一个屏障连续使用 ROUNDS 轮: 每轮离开时所有 hart 都必须已经到达,
+ 且每轮恰好有一个 hart 是最后到达者
+*/
+pub fn run(_hartid: usize) {
+    if participant().is_none() {
+        return;
+    }
+    let harts_under_test = hart::cpu_count();
+
+    for round in 0..ROUNDS {
+        ARRIVALS.fetch_add(1, Ordering::Relaxed);
+        if BARRIER.wait() {
+            LEADERS.fetch_add(1, Ordering::Relaxed);
+        }
+        if ARRIVALS.load(Ordering::Relaxed) < (round + 1) * harts_under_test {
+            EARLY_EXITS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // 再用一轮收集结果, 最后到达者打印
+    if !BARRIER.wait() {
+        return;
+    }
+    let leaders = LEADERS.load(Ordering::Relaxed);
+    let early = EARLY_EXITS.load(Ordering::Relaxed);
+    if leaders == ROUNDS && early == 0 {
+        printk!(
+            "{}[PASS]{} Barrier test: {} rounds on {} harts",
+            ANSI_GREEN,
+            ANSI_RESET,
+            ROUNDS,
+            harts_under_test
+        );
+    } else {
+        printk!(
+            "{}[FAIL]{} Barrier test: {} leaders in {} rounds, {} early exits",
+            ANSI_RED,
+            ANSI_RESET,
+            leaders,
+            ROUNDS,
+            early
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::mm::{frame, heap};
+use crate::printk;
+use crate::printk::{ANSI_BLUE, ANSI_GREEN, ANSI_RED, ANSI_RESET};
+
+// 超过一次扩容 (HEAP_GROW_MIN) 的大小, 确保堆会按需增长
+const VEC_ELEMENTS: usize = 64 * 1024;
+const MAP_ENTRIES: usize = 1024;
+
+pub fn run() {
+    let used_before = heap::stats().used;
+    match heap_test() {
+        Ok(grown) => {
+            let used_after = heap::stats().used;
+            if used_after == used_before {
+                printk!(
+                    "{}[PASS]{} Heap test: heap grew to {} KiB, all memory returned",
+                    ANSI_GREEN,
+                    ANSI_RESET,
+                    grown / 1024
+                );
+            } else {
+                printk!(
+                    "{}[FAIL]{} Heap test: {} bytes still in use (expected {})",
+                    ANSI_RED,
+                    ANSI_RESET,
+                    used_after,
+                    used_before
+                );
+            }
+        }
+        Err(msg) => printk!("{}[FAIL]{} Heap test: {}", ANSI_RED, ANSI_RESET, msg),
+    }
+}
+
+fn heap_test() -> Result<usize, &'static str> {
+    printk!("{}heap test start{}", ANSI_BLUE, ANSI_RESET);
+
+    let boxed = Box::new(0x5a5a_5a5a_u32);
+    if *boxed != 0x5a5a_5a5a {
+        return Err("Box value corrupted");
+    }
+
+    let mut vec = Vec::new();
+    for i in 0..VEC_ELEMENTS {
+        vec.push(i);
+    }
+    if vec.iter().enumerate().any(|(i, &v)| i != v) {
+        return Err("Vec contents corrupted after growth");
+    }
+
+    let mut map = BTreeMap::new();
+    for i in 0..MAP_ENTRIES {
+        map.insert(i * 7 % MAP_ENTRIES, i);
+    }
+    if map.len() != MAP_ENTRIES || map.keys().copied().ne(0..MAP_ENTRIES) {
+        return Err("BTreeMap lost or reordered entries");
+    }
+
+    // 高对齐分配
+    let aligned = Box::new(Aligned([0; 4096]));
+    if !(aligned.as_ref() as *const Aligned as usize).is_multiple_of(4096) || aligned.0[4095] != 0 {
+        return Err("over-aligned allocation is misaligned");
+    }
+
+    // 页帧分配器: 分配后释放, 伙伴应重新合并
+    let (free_held, _) = frame::alloc_frames(0).map_or((0, 0), |pa| {
+        let stats = frame::stats();
+        unsafe { frame::free_frames(pa, 0) };
+        stats
+    });
+    if frame::stats().0 != free_held + 1 {
+        return Err("frame allocator leaked a frame");
+    }
+
+    Ok(heap::stats().size)
+}
+
+#[repr(align(4096))]
+struct Aligned([u8; 4096]);
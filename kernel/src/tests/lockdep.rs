@@ -0,0 +1,99 @@
+#[cfg(debug_assertions)]
+use crate::lock::SpinLock;
+#[cfg(debug_assertions)]
+use crate::lock::lockdep;
+#[cfg(debug_assertions)]
+use crate::percpu;
+use crate::printk;
+#[cfg(debug_assertions)]
+use crate::printk::{ANSI_GREEN, ANSI_RED};
+use crate::printk::{ANSI_RESET, ANSI_YELLOW};
+#[cfg(debug_assertions)]
+use crate::trap;
+
+// 确认报告数量的变化, 其它 hart 同时产生的报告会让测试误判为失败
+#[cfg(debug_assertions)]
+fn expect_reports(before: usize, expected: usize, msg: &'static str) -> Result<(), &'static str> {
+    if !lockdep::is_enabled() {
+        return Err("lockdep turned itself off");
+    }
+    if lockdep::report_count() - before != expected {
+        return Err(msg);
+    }
+    Ok(())
+}
+
+#[cfg(debug_assertions)]
+fn lockdep_test() -> Result<(), &'static str> {
+    let a = SpinLock::new(0usize);
+    let b = SpinLock::new(0usize);
+    let before = lockdep::report_count();
+
+    // 一致的顺序不产生报告
+    for _ in 0..2 {
+        let _a = a.lock();
+        let _b = b.lock();
+    }
+    expect_reports(before, 0, "consistent order reported")?;
+
+    // 反向的顺序报告一次, 重复出现不再报告
+    printk!("{}Lockdep test: expecting one ABBA report{}", ANSI_YELLOW, ANSI_RESET);
+    for _ in 0..2 {
+        let _b = b.lock();
+        let _a = a.lock();
+    }
+    expect_reports(before, 1, "ABBA order not reported exactly once")?;
+
+    // 在陷入处理中获取的锁之后又在开中断的情况下获取
+    let c = SpinLock::new(0usize);
+    percpu::irq_enter();
+    drop(c.lock());
+    percpu::irq_exit();
+    printk!("{}Lockdep test: expecting one irq-unsafe report{}", ANSI_YELLOW, ANSI_RESET);
+    let irq_enabled = trap::local_irq_save();
+    trap::local_irq_restore(true);
+    drop(c.lock());
+    if !irq_enabled {
+        trap::local_irq_save();
+    }
+    expect_reports(before, 2, "irq-unsafe lock not reported")
+}
+
+// 同一处创建的锁, 用 new_with_class 按名字分成不同的锁类
+#[cfg(debug_assertions)]
+fn named_lock(name: &'static str) -> SpinLock<usize> {
+    SpinLock::new_with_class(0, name)
+}
+
+#[cfg(debug_assertions)]
+fn named_class_test() -> Result<(), &'static str> {
+    let outer = named_lock("lockdep-test-outer");
+    let inner = named_lock("lockdep-test-inner");
+    let before = lockdep::report_count();
+
+    // 嵌套获取同一处创建的两把锁, 不是递归加锁
+    for _ in 0..2 {
+        let _outer = outer.lock();
+        let _inner = inner.lock();
+    }
+    expect_reports(before, 0, "nested locks from one site reported")?;
+
+    // 名字不同的锁类之间照常检查加锁顺序
+    printk!("{}Lockdep test: expecting one ABBA report{}", ANSI_YELLOW, ANSI_RESET);
+    let _inner = inner.lock();
+    let _outer = outer.lock();
+    expect_reports(before, 1, "ABBA order between named classes not reported")
+}
+
+pub fn run() {
+    #[cfg(debug_assertions)]
+    for (name, result) in [("Lockdep", lockdep_test()), ("Lockdep named class", named_class_test())]
+    {
+        match result {
+            Ok(()) => printk!("{}[PASS]{} {} test", ANSI_GREEN, ANSI_RESET, name),
+            Err(msg) => printk!("{}[FAIL]{} {} test: {}", ANSI_RED, ANSI_RESET, name, msg),
+        }
+    }
+    #[cfg(not(debug_assertions))]
+    printk!("{}[SKIP]{} Lockdep test: release build", ANSI_YELLOW, ANSI_RESET);
+}
@@ -1,12 +1,80 @@
+mod barrier;
+mod heap;
+mod lockdep;
+mod percpu;
 mod printk;
+mod rcu;
+mod rwlock;
+mod seqlock;
+mod slab;
 mod spinlock;
+mod trace;
+mod watchdog;
 
+use sync::Lazy;
+
+use crate::hart;
+use crate::lock::barrier::Rendezvous;
+
+// 压力测试共用的同步点, 参与的 hart 按相同顺序进入各项测试, 可以连续复用
+static ROUND: Lazy<Rendezvous> = Lazy::new(|| Rendezvous::new(hart::cpu_count()));
+
+// 压力测试的参与者是全部逻辑 CPU, 没有逻辑 CPU 编号的 hart 不参与
+fn participant() -> Option<usize> {
+    crate::percpu!(cpu).get()
+}
+
+pub fn run_percpu_tests(hartid: usize) {
+    percpu::run(hartid);
+}
 pub fn run_spinlock_tests(hartid: usize) {
     spinlock::run(hartid);
 }
+pub fn run_barrier_tests(hartid: usize) {
+    barrier::run(hartid);
+}
+pub fn run_rwlock_tests(hartid: usize) {
+    rwlock::run(hartid);
+}
+pub fn run_seqlock_tests(hartid: usize) {
+    seqlock::run(hartid);
+}
+pub fn run_rcu_tests(hartid: usize) {
+    rcu::run(hartid);
+}
+pub fn run_lockdep_tests(hartid: usize) {
+    if hartid != hart::boot_id() {
+        return;
+    }
+    lockdep::run();
+}
+pub fn run_watchdog_tests(hartid: usize) {
+    if hartid != hart::boot_id() {
+        return;
+    }
+    watchdog::run();
+}
+pub fn run_trace_tests(hartid: usize) {
+    if hartid != hart::boot_id() {
+        return;
+    }
+    trace::run();
+}
 pub fn run_printk_tests(hartid: usize) {
-    if hartid != 0 {
+    if hartid != hart::boot_id() {
         return;
     }
     printk::run();
 }
+pub fn run_heap_tests(hartid: usize) {
+    if hartid != hart::boot_id() {
+        return;
+    }
+    heap::run();
+}
+pub fn run_slab_tests(hartid: usize) {
+    if hartid != hart::boot_id() {
+        return;
+    }
+    slab::run();
+}
@@ -0,0 +1,43 @@
+use core::sync::atomic::Ordering;
+
+use crate::hart;
+use crate::percpu;
+use crate::printk;
+use crate::printk::{ANSI_GREEN, ANSI_RED, ANSI_RESET};
+
+pub fn run(hartid: usize) {
+    match percpu_test(hartid) {
+        Ok(()) => printk!("{}[PASS]{} Per-CPU test on hart {}", ANSI_GREEN, ANSI_RESET, hartid),
+        Err(msg) => {
+            printk!("{}[FAIL]{} Per-CPU test on hart {}: {}", ANSI_RED, ANSI_RESET, hartid, msg)
+        }
+    }
+}
+
+fn percpu_test(hartid: usize) -> Result<(), &'static str> {
+    let this = percpu::this_cpu();
+    if percpu!(hartid).get() != hartid || hart::current_id() != hartid {
+        return Err("tp does not point at this hart's block");
+    }
+    if !core::ptr::eq(this, percpu::of(hartid)) {
+        return Err("this_cpu() and of(hartid) disagree");
+    }
+    if percpu!(cpu).get() != hart::cpu_id(hartid) {
+        return Err("logical CPU number not initialized");
+    }
+    if percpu!(emergency_stack_top).load(Ordering::Acquire) == 0 {
+        return Err("no emergency stack");
+    }
+
+    // 中断嵌套计数
+    let depth = percpu!(irq_depth).get();
+    percpu::irq_enter();
+    percpu::irq_enter();
+    let nested = percpu!(irq_depth).get() == depth + 2 && percpu::in_interrupt();
+    percpu::irq_exit();
+    percpu::irq_exit();
+    if !nested || percpu!(irq_depth).get() != depth {
+        return Err("irq_enter/irq_exit nesting is unbalanced");
+    }
+    Ok(())
+}
@@ -1,10 +1,23 @@
+use alloc::vec;
+
 use crate::printk;
+use crate::printk::logbuf::LOG_BUF_SIZE;
 use crate::printk::{
     ANSI_BLUE, ANSI_CYAN, ANSI_GREEN, ANSI_MAGENTA, ANSI_RED, ANSI_RESET, ANSI_WHITE, ANSI_YELLOW,
+    Level,
 };
+use crate::{pr_debug, pr_err, pr_info, pr_warn};
 pub fn run() {
     printk_test();
     printk!("{}[PASS]{} Printk test", ANSI_GREEN, ANSI_RESET);
+    match level_test() {
+        Ok(()) => printk!("{}[PASS]{} Log level test", ANSI_GREEN, ANSI_RESET),
+        Err(msg) => printk!("{}[FAIL]{} Log level test: {}", ANSI_RED, ANSI_RESET, msg),
+    }
+    match log_buffer_test() {
+        Ok(()) => printk!("{}[PASS]{} Log buffer test", ANSI_GREEN, ANSI_RESET),
+        Err(msg) => printk!("{}[FAIL]{} Log buffer test: {}", ANSI_RED, ANSI_RESET, msg),
+    }
 }
 fn printk_test() {
     printk!("{}printk test start{}", ANSI_BLUE, ANSI_RESET);
@@ -26,3 +39,56 @@ fn printk_test() {
         ANSI_RESET
     );
 }
+// 运行期级别只能在编译期级别之内收紧, 被过滤的调用不求值参数
+fn level_test() -> Result<(), &'static str> {
+    let saved = printk::level();
+    pr_err!("log level test: error");
+    pr_warn!("log level test: warning");
+    pr_info!("log level test: info");
+    pr_debug!("log level test: debug");
+
+    printk::set_level(Level::Warn);
+    let mut evaluated = false;
+    pr_info!("log level test: must not print {}", {
+        evaluated = true;
+        0
+    });
+    let filtered = !printk::enabled(Level::Info) && printk::enabled(Level::Error);
+    printk::set_level(Level::Debug);
+    let clamped = printk::level() == printk::STATIC_LEVEL;
+    printk::set_level(saved);
+
+    if evaluated || !filtered {
+        return Err("runtime level did not filter info messages");
+    }
+    if !clamped {
+        return Err("runtime level exceeded the compile-time level");
+    }
+    Ok(())
+}
+// printk 的输出都能从日志缓冲区读回, 包括控制台注册之前的启动信息
+fn log_buffer_test() -> Result<(), &'static str> {
+    let mut log = vec![0u8; LOG_BUF_SIZE];
+    let start = printk::log_head();
+    printk!("log buffer test marker");
+    // 其它 hart 可能同时输出, 只要求标记出现在 start 之后
+    let mut pos = start;
+    let len = printk::read_log(&mut pos, &mut log);
+    if !contains(&log[..len], b"log buffer test marker\n") {
+        return Err("marker not found in the log buffer");
+    }
+    if pos != start + len {
+        return Err("read position not advanced");
+    }
+
+    let mut pos = 0;
+    let len = printk::read_log(&mut pos, &mut log);
+    if !contains(&log[..len], b"Glenda microkernel booting") {
+        return Err("boot messages missing from the log buffer");
+    }
+    Ok(())
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
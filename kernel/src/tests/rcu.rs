@@ -0,0 +1,126 @@
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use sync::Lazy;
+
+use crate::hart;
+use crate::printk;
+use crate::printk::{ANSI_GREEN, ANSI_RED, ANSI_RESET};
+use crate::rcu::{self, RcuCell};
+
+use super::{ROUND, participant};
+
+const UPDATES: usize = 512;
+const HOLD_SPINS: usize = 64;
+const POISON: usize = 0xdead_beef;
+
+// 释放时写入毒值, 读者看到毒值或不一致的组合说明旧版本被提前释放
+struct Version {
+    n: usize,
+    inverted: usize,
+}
+
+impl Version {
+    fn new(n: usize) -> Self {
+        Self { n, inverted: !n }
+    }
+}
+
+impl Drop for Version {
+    fn drop(&mut self) {
+        self.n = POISON;
+        self.inverted = POISON;
+        DROPS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+static CELL: Lazy<RcuCell<Version>> = Lazy::new(|| RcuCell::new(Version::new(0)));
+static WRITER_DONE: AtomicBool = AtomicBool::new(false);
+static DROPS: AtomicUsize = AtomicUsize::new(0);
+static READS: AtomicUsize = AtomicUsize::new(0);
+static BAD_READS: AtomicUsize = AtomicUsize::new(0);
+static WENT_BACKWARDS: AtomicUsize = AtomicUsize::new(0);
+
+// CPU 0 不断发布新版本, 其余 CPU 在读端临界区内反复检查自己看到的版本
+fn rcu_test(cpu: usize) {
+    Lazy::force(&CELL);
+    ROUND.wait();
+
+    if cpu == 0 {
+        for n in 1..=UPDATES {
+            CELL.replace(Version::new(n));
+        }
+        WRITER_DONE.store(true, Ordering::SeqCst);
+        // 等所有读者离开之后, 被替换的版本都应已释放
+        rcu::synchronize();
+        return;
+    }
+
+    let mut last = 0;
+    let mut reads = 0;
+    loop {
+        let done = WRITER_DONE.load(Ordering::SeqCst);
+        {
+            let guard = rcu::read_lock();
+            let version = CELL.read(&guard);
+            let n = version.n;
+            for _ in 0..HOLD_SPINS {
+                spin_loop();
+            }
+            if version.n != n || version.inverted != !n || n == POISON {
+                BAD_READS.fetch_add(1, Ordering::Relaxed);
+            }
+            if n < last {
+                WENT_BACKWARDS.fetch_add(1, Ordering::Relaxed);
+            }
+            last = n;
+        }
+        reads += 1;
+        // 给时钟中断留出临界区之外的窗口
+        for _ in 0..HOLD_SPINS {
+            spin_loop();
+        }
+        if done {
+            break;
+        }
+    }
+    READS.fetch_add(reads, Ordering::Relaxed);
+}
+
+pub fn run(_hartid: usize) {
+    let Some(cpu) = participant() else {
+        return;
+    };
+    let harts_under_test = hart::cpu_count();
+
+    rcu_test(cpu);
+    ROUND.run(|| report(harts_under_test));
+}
+
+// 由最后完成的 hart 校验结果
+fn report(harts_under_test: usize) {
+    let drops = DROPS.load(Ordering::Relaxed);
+    let bad = BAD_READS.load(Ordering::Relaxed);
+    let backwards = WENT_BACKWARDS.load(Ordering::Relaxed);
+    let latest = CELL.read(&rcu::read_lock()).n;
+    if bad == 0 && backwards == 0 && drops == UPDATES && latest == UPDATES {
+        printk!(
+            "{}[PASS]{} RCU test: {} updates reclaimed, {} reads on {} readers",
+            ANSI_GREEN,
+            ANSI_RESET,
+            UPDATES,
+            READS.load(Ordering::Relaxed),
+            harts_under_test - 1
+        );
+    } else {
+        printk!(
+            "{}[FAIL]{} RCU test: {} bad reads, {} went backwards, {} of {} versions reclaimed",
+            ANSI_RED,
+            ANSI_RESET,
+            bad,
+            backwards,
+            drops,
+            UPDATES
+        );
+    }
+}
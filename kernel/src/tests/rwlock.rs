@@ -0,0 +1,169 @@
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::hart;
+use crate::lock::SpinWait;
+use crate::lock::rwlock::RwSpinLock;
+use crate::printk;
+use crate::printk::{ANSI_GREEN, ANSI_RED, ANSI_RESET};
+
+use super::{ROUND, participant};
+
+const ITERATIONS_PER_HART: usize = 256;
+// 每 WRITE_EVERY 轮中有一轮写
+const WRITE_EVERY: usize = 8;
+const HOLD_SPINS: usize = 32;
+// 写者优先测试中等待写者出现的最大轮数
+const PREFERENCE_SPINS: usize = 10_000_000;
+
+// 写者总是同时修改两个字段, 读者看到两者不等说明互斥失效
+struct Pair {
+    a: usize,
+    b: usize,
+}
+
+static DATA: RwSpinLock<Pair> = RwSpinLock::new(Pair { a: 0, b: 0 });
+static READERS_INSIDE: AtomicUsize = AtomicUsize::new(0);
+static MAX_READERS: AtomicUsize = AtomicUsize::new(0);
+static TORN_READS: AtomicUsize = AtomicUsize::new(0);
+
+static PREFERENCE_LOCK: RwSpinLock<usize> = RwSpinLock::new(0);
+static READER_HOLDING: AtomicBool = AtomicBool::new(false);
+
+// 守卫语义: 多个读者可以共存, 读者与写者互斥
+fn guard_test() -> Result<(), &'static str> {
+    let lock = RwSpinLock::new(0usize);
+    {
+        let first = lock.read();
+        let second = lock.try_read().ok_or("second reader refused")?;
+        if lock.readers() != 2 || *first != *second {
+            return Err("reader count wrong");
+        }
+        if lock.try_write().is_some() {
+            return Err("try_write succeeded while read-locked");
+        }
+    }
+    {
+        let mut guard = lock.write();
+        *guard += 1;
+        if lock.try_read().is_some() || lock.try_write().is_some() {
+            return Err("lock not exclusive while write-locked");
+        }
+    }
+    if lock.is_write_locked() || lock.readers() != 0 {
+        return Err("guards did not unlock");
+    }
+    if lock.try_write().map(|guard| *guard) != Some(1) {
+        return Err("protected data lost");
+    }
+    Ok(())
+}
+
+fn stress(cpu: usize) {
+    for iter in 0..ITERATIONS_PER_HART {
+        if (iter + cpu).is_multiple_of(WRITE_EVERY) {
+            let mut pair = DATA.write();
+            pair.a += 1;
+            for _ in 0..HOLD_SPINS {
+                spin_loop();
+            }
+            pair.b += 1;
+        } else {
+            let pair = DATA.read();
+            let inside = READERS_INSIDE.fetch_add(1, Ordering::Relaxed) + 1;
+            MAX_READERS.fetch_max(inside, Ordering::Relaxed);
+            if pair.a != pair.b {
+                TORN_READS.fetch_add(1, Ordering::Relaxed);
+            }
+            for _ in 0..HOLD_SPINS {
+                spin_loop();
+            }
+            READERS_INSIDE.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/*
+ 写者优先: CPU 0 持有读锁, CPU 1 开始等待写锁之后, 新的读者必须被拒绝
+*/
+fn preference_test(cpu: usize) -> Option<Result<(), &'static str>> {
+    match cpu {
+        0 => {
+            let guard = PREFERENCE_LOCK.read();
+            READER_HOLDING.store(true, Ordering::SeqCst);
+            let refused = (0..PREFERENCE_SPINS).any(|_| PREFERENCE_LOCK.try_read().is_none());
+            drop(guard);
+            Some(if refused { Ok(()) } else { Err("new reader admitted while a writer waits") })
+        }
+        1 => {
+            let mut wait = SpinWait::new(&READER_HOLDING);
+            while !READER_HOLDING.load(Ordering::SeqCst) {
+                wait.spin();
+            }
+            *PREFERENCE_LOCK.write() += 1;
+            None
+        }
+        _ => None,
+    }
+}
+
+pub fn run(hartid: usize) {
+    if hartid == hart::boot_id() {
+        match guard_test() {
+            Ok(()) => printk!("{}[PASS]{} RwSpinLock guard test", ANSI_GREEN, ANSI_RESET),
+            Err(msg) => printk!("{}[FAIL]{} RwSpinLock guard test: {}", ANSI_RED, ANSI_RESET, msg),
+        }
+    }
+
+    let Some(cpu) = participant() else {
+        return;
+    };
+    let harts_under_test = hart::cpu_count();
+
+    ROUND.wait();
+    stress(cpu);
+    // 最后完成的 hart 校验结果, 返回时所有 hart 都已离开 stress
+    ROUND.run(|| report_stress(harts_under_test));
+
+    if harts_under_test < 2 {
+        return;
+    }
+    match preference_test(cpu) {
+        Some(Ok(())) => {
+            printk!("{}[PASS]{} RwSpinLock writer preference test", ANSI_GREEN, ANSI_RESET)
+        }
+        Some(Err(msg)) => {
+            printk!("{}[FAIL]{} RwSpinLock writer preference test: {}", ANSI_RED, ANSI_RESET, msg)
+        }
+        None => {}
+    }
+}
+
+fn report_stress(harts_under_test: usize) {
+    let writes = (0..harts_under_test)
+        .map(|cpu| {
+            (0..ITERATIONS_PER_HART).filter(|iter| (iter + cpu).is_multiple_of(WRITE_EVERY)).count()
+        })
+        .sum::<usize>();
+    let pair = DATA.read();
+    let torn = TORN_READS.load(Ordering::Relaxed);
+    if torn == 0 && pair.a == writes && pair.b == writes {
+        printk!(
+            "{}[PASS]{} RwSpinLock test: {} writes, up to {} concurrent readers",
+            ANSI_GREEN,
+            ANSI_RESET,
+            writes,
+            MAX_READERS.load(Ordering::Relaxed)
+        );
+    } else {
+        printk!(
+            "{}[FAIL]{} RwSpinLock test: {} torn reads, counters {}/{} (expected {})",
+            ANSI_RED,
+            ANSI_RESET,
+            torn,
+            pair.a,
+            pair.b,
+            writes
+        );
+    }
+}
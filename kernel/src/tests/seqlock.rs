@@ -0,0 +1,103 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::hart;
+use crate::lock::seqlock::SeqLock;
+use crate::printk;
+use crate::printk::{ANSI_GREEN, ANSI_RED, ANSI_RESET};
+
+use super::{ROUND, participant};
+
+const WRITES: usize = 4096;
+
+// 三个字段由同一个 n 推出, 读到不一致的组合说明读到了写到一半的记录
+#[derive(Clone, Copy)]
+struct Record {
+    n: usize,
+    triple: usize,
+    inverted: usize,
+}
+
+impl Record {
+    const fn new(n: usize) -> Self {
+        Self { n, triple: n.wrapping_mul(3), inverted: !n }
+    }
+
+    fn is_consistent(&self) -> bool {
+        self.triple == self.n.wrapping_mul(3) && self.inverted == !self.n
+    }
+}
+
+static RECORD: SeqLock<Record> = SeqLock::new(Record::new(0));
+static WRITER_DONE: AtomicBool = AtomicBool::new(false);
+static READS: AtomicUsize = AtomicUsize::new(0);
+static TORN_READS: AtomicUsize = AtomicUsize::new(0);
+// 同一读者先后读到的序号变小
+static WENT_BACKWARDS: AtomicUsize = AtomicUsize::new(0);
+
+// CPU 0 连续写入, 其余 CPU 不停读取并校验, 直到写者结束
+fn seqlock_test(cpu: usize) {
+    ROUND.wait();
+
+    if cpu == 0 {
+        for n in 1..=WRITES {
+            RECORD.set(Record::new(n));
+        }
+        WRITER_DONE.store(true, Ordering::SeqCst);
+        return;
+    }
+
+    let mut last = 0;
+    let mut reads = 0;
+    loop {
+        let done = WRITER_DONE.load(Ordering::SeqCst);
+        let record = RECORD.read();
+        reads += 1;
+        if !record.is_consistent() {
+            TORN_READS.fetch_add(1, Ordering::Relaxed);
+        }
+        if record.n < last {
+            WENT_BACKWARDS.fetch_add(1, Ordering::Relaxed);
+        }
+        last = record.n;
+        if done {
+            break;
+        }
+    }
+    READS.fetch_add(reads, Ordering::Relaxed);
+}
+
+pub fn run(_hartid: usize) {
+    let Some(cpu) = participant() else {
+        return;
+    };
+    let harts_under_test = hart::cpu_count();
+
+    seqlock_test(cpu);
+    ROUND.run(|| report(harts_under_test));
+}
+
+// 由最后完成的 hart 校验结果
+fn report(harts_under_test: usize) {
+    let record = RECORD.read();
+    let torn = TORN_READS.load(Ordering::Relaxed);
+    let backwards = WENT_BACKWARDS.load(Ordering::Relaxed);
+    if torn == 0 && backwards == 0 && record.n == WRITES && RECORD.sequence() == WRITES {
+        printk!(
+            "{}[PASS]{} SeqLock test: {} writes, {} consistent reads on {} readers",
+            ANSI_GREEN,
+            ANSI_RESET,
+            WRITES,
+            READS.load(Ordering::Relaxed),
+            harts_under_test - 1
+        );
+    } else {
+        printk!(
+            "{}[FAIL]{} SeqLock test: {} torn reads, {} went backwards, final record {}",
+            ANSI_RED,
+            ANSI_RESET,
+            torn,
+            backwards,
+            record.n
+        );
+    }
+}
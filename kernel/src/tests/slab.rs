@@ -0,0 +1,74 @@
+use core::ptr::NonNull;
+
+use crate::mm::slab::ObjectCache;
+use crate::printk;
+use crate::printk::{ANSI_BLUE, ANSI_GREEN, ANSI_RED, ANSI_RESET};
+
+// 足够多, 让 cache 跨越多个 slab 并多次与 depot 交换 magazine
+const OBJECTS: usize = 200;
+
+#[repr(align(64))]
+struct TestObject {
+    id: usize,
+    payload: [u64; 12],
+}
+
+static TEST_CACHE: ObjectCache<TestObject> = ObjectCache::new("slab-test");
+
+pub fn run() {
+    match slab_test() {
+        Ok(slabs) => printk!(
+            "{}[PASS]{} Slab test: {} objects in {} slabs, all returned",
+            ANSI_GREEN,
+            ANSI_RESET,
+            OBJECTS,
+            slabs
+        ),
+        Err(msg) => printk!("{}[FAIL]{} Slab test: {}", ANSI_RED, ANSI_RESET, msg),
+    }
+}
+
+fn slab_test() -> Result<usize, &'static str> {
+    printk!("{}slab test start{}", ANSI_BLUE, ANSI_RESET);
+
+    let mut objects: [Option<NonNull<TestObject>>; OBJECTS] = [None; OBJECTS];
+    for (id, slot) in objects.iter_mut().enumerate() {
+        let object = TEST_CACHE
+            .alloc(TestObject { id, payload: [id as u64; 12] })
+            .ok_or("cache ran out of memory")?;
+        if !(object.as_ptr() as usize).is_multiple_of(64) {
+            return Err("object is misaligned");
+        }
+        *slot = Some(object);
+    }
+
+    // 对象之间互不重叠: 逐个检查内容没有被其它对象覆盖
+    for (id, object) in objects.iter().enumerate() {
+        let object = unsafe { object.unwrap().as_ref() };
+        if object.id != id || object.payload.iter().any(|&word| word != id as u64) {
+            return Err("objects overlap");
+        }
+    }
+
+    let stats = TEST_CACHE.stats();
+    if stats.in_use != OBJECTS || stats.total_objects < OBJECTS {
+        return Err("stats disagree with allocations");
+    }
+
+    for object in objects.iter_mut() {
+        unsafe { TEST_CACHE.free(object.take().unwrap()) };
+    }
+
+    // 释放后再分配会复用对象, debug 构建中同时检查了毒化字节
+    let again = TEST_CACHE.alloc(TestObject { id: 0, payload: [0; 12] }).ok_or("realloc failed")?;
+    unsafe { TEST_CACHE.free(again) };
+
+    let after = TEST_CACHE.stats();
+    if after.in_use != 0 {
+        return Err("objects still in use after free");
+    }
+    if after.slabs != stats.slabs {
+        return Err("cache grew although freed objects were available");
+    }
+    Ok(after.slabs)
+}
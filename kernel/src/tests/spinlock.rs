@@ -1,69 +1,75 @@
 use core::hint::spin_loop;
-use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-use crate::dtb;
-use crate::lock::SpinLock;
+use riscv::register::{sstatus, time};
+
+use crate::hart::{self, MAX_HARTS};
+use crate::lock::{
+    Lock, McsLock, RawLock, RawMcsLock, RawSpinLock, RawTicketLock, SpinLock, TicketLock,
+};
 use crate::printk;
 use crate::printk::{ANSI_BLUE, ANSI_GREEN, ANSI_RED, ANSI_RESET, ANSI_YELLOW};
 
+use super::{ROUND, participant};
+
 const INCREMENTS_PER_HART: usize = 16;
 
-// 被测试的自旋锁
-static TEST_LOCK: SpinLock = SpinLock::new();
-// 计数
-static GLOBAL_COUNTER: AtomicUsize = AtomicUsize::new(0);
-// 参与 hart 数量，此变量用来确保所有 hart 同步启动
-static PARTICIPANTS: AtomicUsize = AtomicUsize::new(0);
-static START_TEST: AtomicBool = AtomicBool::new(false);
-static HARTS_FINISHED: AtomicUsize = AtomicUsize::new(0);
-
-// 自旋锁一致性测试
-fn spinlock_test(hartid: usize, harts_under_test: usize) -> usize {
-    if hartid >= harts_under_test {
-        return 0;
+// 被测试的自旋锁, 保护计数
+static TEST_LOCK: SpinLock<usize> = SpinLock::new(0);
+
+// 竞争测试: 每轮共 ACQUISITIONS_PER_HART * harts 次加锁, 持锁期间空转 HOLD_SPINS 次
+const ACQUISITIONS_PER_HART: usize = 256;
+const HOLD_SPINS: usize = 64;
+
+struct Contention {
+    remaining: usize,
+    // 按逻辑 CPU 统计的加锁次数与最长等待 (time 计数)
+    acquisitions: [usize; MAX_HARTS],
+    worst_wait: [usize; MAX_HARTS],
+}
+
+impl Contention {
+    const fn new() -> Self {
+        Self { remaining: 0, acquisitions: [0; MAX_HARTS], worst_wait: [0; MAX_HARTS] }
+    }
+}
+
+static SPIN_CONTENTION: SpinLock<Contention> = SpinLock::new(Contention::new());
+static TICKET_CONTENTION: TicketLock<Contention> = TicketLock::new(Contention::new());
+static MCS_CONTENTION: McsLock<Contention> = McsLock::new(Contention::new());
+
+// 自旋锁一致性测试, 返回本 hart 是否参与
+fn spinlock_test(hartid: usize, harts_under_test: usize) -> bool {
+    if participant().is_none() {
+        return false;
     }
 
     // 确保所有 hart 统一开始
-    let ready = PARTICIPANTS.fetch_add(1, Ordering::SeqCst) + 1;
-    if ready == harts_under_test {
+    ROUND.run(|| {
         printk!(
             "{}All {} harts ready. Starting spinlock test{}",
             ANSI_BLUE,
             harts_under_test,
             ANSI_RESET
-        );
-        START_TEST.store(true, Ordering::SeqCst);
-    } else {
-        while !START_TEST.load(Ordering::SeqCst) {
-            spin_loop();
-        }
-    }
+        )
+    });
 
-    // 拿锁 && 解锁
+    // 拿锁 && 解锁 (守卫离开作用域时解锁), 奇数轮使用 lock_irqsave
     for iter in 0..INCREMENTS_PER_HART {
-        TEST_LOCK.lock();
-        let value_before = GLOBAL_COUNTER.load(Ordering::Relaxed);
-        driver_uart::print!("[hart {}] iter {} -> counter {}\n", hartid, iter, value_before + 1);
-        GLOBAL_COUNTER.store(value_before + 1, Ordering::Relaxed);
-        TEST_LOCK.unlock();
+        if iter % 2 == 0 {
+            let mut counter = TEST_LOCK.lock();
+            driver_uart::print!("[hart {}] iter {} -> counter {}\n", hartid, iter, *counter + 1);
+            *counter += 1;
+        } else {
+            let mut counter = TEST_LOCK.lock_irqsave();
+            driver_uart::print!("[hart {}] iter {} -> counter {}\n", hartid, iter, *counter + 1);
+            *counter += 1;
+        }
     }
 
-    return HARTS_FINISHED.fetch_add(1, Ordering::SeqCst) + 1;
-}
-
-pub fn run(hartid: usize) {
-    let harts_under_test = dtb::hart_count();
-    // 运行测试
-    let result = spinlock_test(hartid, harts_under_test);
-
-    // 校验 + 打印结果
-    if result == 0 {
-        printk!("{}hart {} idle{} (not part of spinlock test)", ANSI_YELLOW, hartid, ANSI_RESET);
-        return;
-    }
-    if result == harts_under_test {
+    // 最后完成的 hart 校验结果
+    ROUND.run(|| {
         let expected = harts_under_test * INCREMENTS_PER_HART;
-        let final_value = GLOBAL_COUNTER.load(Ordering::SeqCst);
+        let final_value = *TEST_LOCK.lock();
         if final_value == expected {
             printk!(
                 "{}[PASS]{} Spinlock test: counter reached {}",
@@ -80,5 +86,136 @@ pub fn run(hartid: usize) {
                 expected
             );
         }
+    });
+    true
+}
+
+// 各 hart 抢同一把锁直到配额用完, 记录每个 hart 抢到的次数与最长等待时间
+fn contention_round<R: RawLock + Sync>(
+    name: &str,
+    lock: &Lock<R, Contention>,
+    cpu: usize,
+    harts_under_test: usize,
+) {
+    let total = harts_under_test * ACQUISITIONS_PER_HART;
+    ROUND.run(|| {
+        let mut stats = lock.lock();
+        *stats = Contention::new();
+        stats.remaining = total;
+    });
+
+    loop {
+        let start = time::read();
+        let mut stats = lock.lock();
+        let wait = time::read().wrapping_sub(start);
+        if stats.remaining == 0 {
+            break;
+        }
+        stats.remaining -= 1;
+        stats.acquisitions[cpu] += 1;
+        stats.worst_wait[cpu] = stats.worst_wait[cpu].max(wait);
+        for _ in 0..HOLD_SPINS {
+            spin_loop();
+        }
     }
+
+    ROUND.run(|| report_contention(name, &lock.lock(), total, harts_under_test));
+}
+
+fn report_contention(name: &str, stats: &Contention, total: usize, harts_under_test: usize) {
+    let acquisitions = &stats.acquisitions[..harts_under_test];
+    let worst_wait = &stats.worst_wait[..harts_under_test];
+    let counted: usize = acquisitions.iter().sum();
+    if counted == total {
+        printk!(
+            "{}[PASS]{} {} contention: {} acquisitions, per hart {:?}, worst wait {:?} ticks (max {})",
+            ANSI_GREEN,
+            ANSI_RESET,
+            name,
+            total,
+            acquisitions,
+            worst_wait,
+            worst_wait.iter().max().unwrap_or(&0)
+        );
+    } else {
+        printk!(
+            "{}[FAIL]{} {} contention: counted {} acquisitions (expected {})",
+            ANSI_RED,
+            ANSI_RESET,
+            name,
+            counted,
+            total
+        );
+    }
+}
+
+// 三种锁依次竞争, 用于比较公平性与最坏等待
+fn contention_test(harts_under_test: usize) {
+    let Some(cpu) = participant() else {
+        return;
+    };
+    contention_round("Spinlock", &SPIN_CONTENTION, cpu, harts_under_test);
+    contention_round("Ticket lock", &TICKET_CONTENTION, cpu, harts_under_test);
+    contention_round("MCS lock", &MCS_CONTENTION, cpu, harts_under_test);
+}
+
+// 守卫语义: 持有期间 try_lock 失败, 释放后可以再次获取, lock_irqsave 恢复中断状态
+fn guard_test<R: RawLock>(hartid: usize) -> Result<(), &'static str> {
+    let lock = Lock::<R, usize>::new(0);
+    {
+        let mut guard = lock.lock();
+        *guard += 1;
+        if lock.try_lock().is_some() {
+            return Err("try_lock succeeded while held");
+        }
+        if cfg!(debug_assertions) && lock.owner() != Some(hartid) {
+            return Err("owner not recorded");
+        }
+    }
+    if lock.is_locked() || lock.owner().is_some() {
+        return Err("guard did not unlock");
+    }
+
+    let irq_before = sstatus::read().sie();
+    {
+        let mut guard = lock.lock_irqsave();
+        *guard += 1;
+        if sstatus::read().sie() {
+            return Err("lock_irqsave left interrupts enabled");
+        }
+    }
+    if sstatus::read().sie() != irq_before {
+        return Err("lock_irqsave did not restore sstatus.SIE");
+    }
+    if lock.try_lock().map(|guard| *guard) != Some(2) {
+        return Err("protected data lost");
+    }
+    Ok(())
+}
+
+pub fn run(hartid: usize) {
+    if hartid == hart::boot_id() {
+        let results = [
+            ("Spinlock", guard_test::<RawSpinLock>(hartid)),
+            ("Ticket lock", guard_test::<RawTicketLock>(hartid)),
+            ("MCS lock", guard_test::<RawMcsLock>(hartid)),
+        ];
+        for (name, result) in results {
+            match result {
+                Ok(()) => printk!("{}[PASS]{} {} guard test", ANSI_GREEN, ANSI_RESET, name),
+                Err(msg) => {
+                    printk!("{}[FAIL]{} {} guard test: {}", ANSI_RED, ANSI_RESET, name, msg)
+                }
+            }
+        }
+    }
+
+    let harts_under_test = hart::cpu_count();
+    // 运行测试, 结果由最后完成的 hart 打印
+    if !spinlock_test(hartid, harts_under_test) {
+        printk!("{}hart {} idle{} (not part of spinlock test)", ANSI_YELLOW, hartid, ANSI_RESET);
+        return;
+    }
+
+    contention_test(harts_under_test);
 }
@@ -0,0 +1,46 @@
+use alloc::vec::Vec;
+
+use crate::printk;
+use crate::printk::{ANSI_GREEN, ANSI_RED, ANSI_RESET};
+use crate::trace::{Event, Phase, Ring};
+
+const CAPACITY: usize = 8;
+
+// 写满之后保留最新的 CAPACITY 条记录, 按写入顺序读出
+fn ring_test() -> Result<(), &'static str> {
+    let ring = Ring::new(CAPACITY);
+    for i in 0..5 {
+        ring.push(i, Phase::Instant, Event::Mark, [i, !i]);
+    }
+    let mut records = Vec::new();
+    ring.for_each(|record| records.push(record));
+    if records.len() != 5 || ring.lost() != 0 {
+        return Err("partially filled ring returned the wrong records");
+    }
+    if records[3] != [3, Event::Mark as u64, 3, !3] {
+        return Err("record words are not laid out as documented");
+    }
+
+    for i in 5..20 {
+        ring.push(i, Phase::Begin, Event::Trap, [i, 0]);
+    }
+    records.clear();
+    ring.for_each(|record| records.push(record));
+    if records.len() != CAPACITY || ring.lost() != 20 - CAPACITY {
+        return Err("full ring did not keep exactly its capacity");
+    }
+    if records.iter().map(|record| record[0]).ne(20 - CAPACITY as u64..20) {
+        return Err("full ring did not keep the newest records in order");
+    }
+    if records[0][1] != Event::Trap as u64 | (Phase::Begin as u64) << 16 {
+        return Err("phase is not encoded in the event word");
+    }
+    Ok(())
+}
+
+pub fn run() {
+    match ring_test() {
+        Ok(()) => printk!("{}[PASS]{} Trace ring test", ANSI_GREEN, ANSI_RESET),
+        Err(msg) => printk!("{}[FAIL]{} Trace ring test: {}", ANSI_RED, ANSI_RESET, msg),
+    }
+}
@@ -0,0 +1,56 @@
+use core::sync::atomic::AtomicBool;
+
+use crate::lock::SpinWait;
+use crate::printk;
+use crate::printk::{ANSI_GREEN, ANSI_RED, ANSI_RESET, ANSI_YELLOW};
+use crate::timer;
+use crate::watchdog;
+
+// 等待时钟中断或超时报告的最长时间
+const TEST_DEADLINE_MS: usize = 1000;
+const SPIN_TIMEOUT_MS: usize = 20;
+
+// 时钟中断按时到达, watchdog 的计时才有意义
+fn tick_test() -> Result<(), &'static str> {
+    let before = watchdog::ticks();
+    let start = timer::now();
+    let deadline = timer::ms_to_ticks(TEST_DEADLINE_MS);
+    while watchdog::ticks() == before {
+        if timer::now().wrapping_sub(start) > deadline {
+            return Err("no timer interrupt within a second");
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+// 一个永远不会被设置的标志, 等待应当超时并报告
+fn spin_timeout_test() -> Result<(), &'static str> {
+    static NEVER: AtomicBool = AtomicBool::new(false);
+    printk!("{}Spin timeout test: expecting one timeout report{}", ANSI_YELLOW, ANSI_RESET);
+    let mut wait = SpinWait::new(&NEVER).with_timeout(SPIN_TIMEOUT_MS);
+    let start = timer::now();
+    let deadline = timer::ms_to_ticks(TEST_DEADLINE_MS);
+    while !wait.timed_out() {
+        if timer::now().wrapping_sub(start) > deadline {
+            return Err("spin wait never timed out");
+        }
+        wait.spin();
+    }
+    Ok(())
+}
+
+pub fn run() {
+    match tick_test() {
+        Ok(()) => printk!("{}[PASS]{} Timer tick test", ANSI_GREEN, ANSI_RESET),
+        Err(msg) => printk!("{}[FAIL]{} Timer tick test: {}", ANSI_RED, ANSI_RESET, msg),
+    }
+    if cfg!(not(debug_assertions)) {
+        printk!("{}[SKIP]{} Spin timeout test: release build", ANSI_YELLOW, ANSI_RESET);
+        return;
+    }
+    match spin_timeout_test() {
+        Ok(()) => printk!("{}[PASS]{} Spin timeout test", ANSI_GREEN, ANSI_RESET),
+        Err(msg) => printk!("{}[FAIL]{} Spin timeout test: {}", ANSI_RED, ANSI_RESET, msg),
+    }
+}
@@ -0,0 +1,81 @@
+#![allow(dead_code)]
+
+use core::arch::asm;
+
+use riscv::register::{sie, sstatus, time};
+
+use crate::dtb;
+use crate::rcu;
+use crate::watchdog;
+
+/*
+ 时钟中断
+
+ 每个 hart 通过 SBI TIME 扩展[1] 设置下一次时钟中断, 每秒 TICK_HZ 次,
+ 时钟中断驱动 watchdog, 并作为 RCU 的静止状态
+
+ time CSR 的频率取自设备树的 timebase-frequency, 缺失时使用 QEMU virt 的 10 MHz
+
+ [1]: https://www.scs.stanford.edu/~zyedidia/docs/riscv/riscv-sbi.pdf, Chapter Six
+
+ Also see:
+ Glenda/kernel/src/watchdog.rs
+ Glenda/kernel/src/rcu.rs
+ Glenda/kernel/src/trap/mod.rs
+*/
+const SBI_EXT_TIME: usize = 0x54494d45;
+const SBI_FUNC_SET_TIMER: usize = 0;
+
+const DEFAULT_TIMEBASE_FREQUENCY: usize = 10_000_000;
+pub const TICK_HZ: usize = 100;
+
+#[inline(always)]
+fn sbi_set_timer(deadline: usize) {
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") deadline => _,
+            lateout("a1") _,
+            in("a6") SBI_FUNC_SET_TIMER,
+            in("a7") SBI_EXT_TIME,
+            options(nostack)
+        );
+    }
+}
+
+pub fn now() -> usize {
+    time::read()
+}
+
+pub fn frequency() -> usize {
+    dtb::timebase_frequency().unwrap_or(DEFAULT_TIMEBASE_FREQUENCY)
+}
+
+pub fn ticks_to_ms(ticks: usize) -> usize {
+    ticks / (frequency() / 1000).max(1)
+}
+
+pub fn ms_to_ticks(ms: usize) -> usize {
+    ms * (frequency() / 1000).max(1)
+}
+
+fn program_next_tick() {
+    sbi_set_timer(now() + frequency() / TICK_HZ);
+}
+
+// 在每个 hart 上调用一次, 之后本 hart 开中断运行
+pub fn init() {
+    watchdog::arm();
+    program_next_tick();
+    unsafe {
+        sie::set_stimer();
+        sstatus::set_sie();
+    }
+}
+
+// 由 kernel_trap 在时钟中断中调用, sepc 为被打断的位置
+pub fn tick(sepc: usize) {
+    program_next_tick();
+    watchdog::tick(sepc);
+    rcu::tick();
+}
@@ -0,0 +1,214 @@
+#![allow(dead_code)]
+
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use sync::OnceCell;
+
+use crate::hart::{self, MAX_HARTS};
+use crate::printk;
+use crate::timer;
+
+/*
+ 二进制事件跟踪 (类似 ftrace)
+
+ 每个 hart 一个环形缓冲区, 只由本 hart 写入, 写满之后覆盖最旧的记录.
+ 一条记录 4 个字:
+
+   w0  时间戳 (time CSR 的 tick)
+   w1  事件编号 | 阶段 << 16
+   w2  参数 0
+   w3  参数 1
+
+ 写入时先用 fetch_add 占位再逐字写入, 因此中断打断写入也不会相互覆盖;
+ 读者看到的记录可能是半写的, dump() 读之前先暂停记录
+
+   trace!(IpiSend, hartid, reason);     // 瞬时事件, 至多 MAX_ARGS 个参数
+   trace!(begin Trap, scause);          // 区间的开始与结束
+   trace!(end Trap);
+
+ 没有打开 trace feature 时 trace! 不生成任何代码, 也不分配缓冲区.
+ dump() 把缓冲区以 "[trace] ..." 行的形式打印到控制台, panic 时自动调用;
+ `cargo xtask trace` 从串口日志中取出这些行, 生成 Chrome/Perfetto 的 JSON 时间线
+
+ Also see:
+ Glenda/kernel/src/panic.rs
+ Glenda/xtask/src/trace.rs
+*/
+// 每个 hart 的记录数, 必须是 2 的幂
+pub const TRACE_EVENTS: usize = 1024;
+pub const MAX_ARGS: usize = 2;
+const RECORD_WORDS: usize = 2 + MAX_ARGS;
+
+#[repr(u16)]
+#[derive(Clone, Copy)]
+pub enum Event {
+    // 参数: scause
+    Trap,
+    // 参数: 目标 hart, 原因位
+    IpiSend,
+    // 参数: 宽限期序号
+    RcuGracePeriod,
+    RcuSynchronize,
+    // 临时调试用
+    Mark,
+}
+
+impl Event {
+    pub const ALL: &[Event] =
+        &[Event::Trap, Event::IpiSend, Event::RcuGracePeriod, Event::RcuSynchronize, Event::Mark];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::Trap => "trap",
+            Event::IpiSend => "ipi_send",
+            Event::RcuGracePeriod => "rcu_grace_period",
+            Event::RcuSynchronize => "rcu_synchronize",
+            Event::Mark => "mark",
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy)]
+pub enum Phase {
+    Instant = 0,
+    Begin = 1,
+    End = 2,
+}
+
+pub struct Ring {
+    // 自创建以来写入的记录总数
+    head: AtomicUsize,
+    slots: Box<[[AtomicU64; RECORD_WORDS]]>,
+}
+
+impl Ring {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity.is_power_of_two() || capacity == 0);
+        let slots = (0..capacity).map(|_| [const { AtomicU64::new(0) }; RECORD_WORDS]).collect();
+        Self { head: AtomicUsize::new(0), slots }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn push(&self, timestamp: u64, phase: Phase, event: Event, args: [u64; MAX_ARGS]) {
+        if self.slots.is_empty() {
+            return;
+        }
+        let index = self.head.fetch_add(1, Ordering::Relaxed) & (self.slots.len() - 1);
+        let slot = &self.slots[index];
+        slot[0].store(timestamp, Ordering::Relaxed);
+        slot[1].store(event as u64 | (phase as u64) << 16, Ordering::Relaxed);
+        for (word, arg) in slot[2..].iter().zip(args) {
+            word.store(arg, Ordering::Relaxed);
+        }
+    }
+
+    // 已被覆盖的记录数
+    pub fn lost(&self) -> usize {
+        self.head.load(Ordering::Acquire).saturating_sub(self.capacity())
+    }
+
+    // 按写入顺序交出仍保留的记录
+    pub fn for_each(&self, mut f: impl FnMut([u64; RECORD_WORDS])) {
+        let head = self.head.load(Ordering::Acquire);
+        for seq in head.saturating_sub(self.capacity())..head {
+            let slot = &self.slots[seq & (self.slots.len() - 1)];
+            f(core::array::from_fn(|i| slot[i].load(Ordering::Relaxed)));
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+// 以 hartid 为下标
+static RINGS: OnceCell<Box<[Ring]>> = OnceCell::new();
+
+// 在每个 hart 上调用, 第一次调用时为所有 hart 分配缓冲区
+pub fn init() {
+    if !cfg!(feature = "trace") {
+        return;
+    }
+    RINGS.get_or_init(|| {
+        (0..MAX_HARTS)
+            .map(|hartid| Ring::new(if hart::cpu_id(hartid).is_some() { TRACE_EVENTS } else { 0 }))
+            .collect()
+    });
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Release);
+}
+
+// 由 trace! 调用
+pub fn record<const N: usize>(phase: Phase, event: Event, args: [u64; N]) {
+    const { assert!(N <= MAX_ARGS, "too many trace arguments") };
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(ring) = RINGS.get().and_then(|rings| rings.get(hart::current_id())) else {
+        return;
+    };
+    let mut words = [0; MAX_ARGS];
+    words[..N].copy_from_slice(&args);
+    ring.push(timer::now() as u64, phase, event, words);
+}
+
+/*
+ 以文本行的形式打印全部缓冲区, 格式见 xtask/src/trace.rs:
+   [trace] begin <timebase-frequency>
+   [trace] event <id> <name>
+   [trace] lost <hartid> <count>
+   [trace] <hartid> <w0> <w1> <w2> <w3>      每个字为十六进制
+   [trace] end
+*/
+pub fn dump() {
+    let Some(rings) = RINGS.get() else {
+        return;
+    };
+    let enabled = ENABLED.swap(false, Ordering::AcqRel);
+    printk!("[trace] begin {}", timer::frequency());
+    for &event in Event::ALL {
+        printk!("[trace] event {} {}", event as u16, event.name());
+    }
+    for (hartid, ring) in rings.iter().enumerate().filter(|(_, ring)| ring.capacity() > 0) {
+        if ring.lost() > 0 {
+            printk!("[trace] lost {} {}", hartid, ring.lost());
+        }
+        ring.for_each(|[w0, w1, w2, w3]| {
+            printk!("[trace] {} {:x} {:x} {:x} {:x}", hartid, w0, w1, w2, w3)
+        });
+    }
+    printk!("[trace] end");
+    ENABLED.store(enabled, Ordering::Release);
+}
+
+#[macro_export]
+macro_rules! trace {
+    (begin $event:ident $(, $arg:expr)* $(,)?) => {
+        $crate::__trace!(Begin, $event $(, $arg)*)
+    };
+    (end $event:ident $(, $arg:expr)* $(,)?) => {
+        $crate::__trace!(End, $event $(, $arg)*)
+    };
+    ($event:ident $(, $arg:expr)* $(,)?) => {
+        $crate::__trace!(Instant, $event $(, $arg)*)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace {
+    ($phase:ident, $event:ident $(, $arg:expr)*) => {
+        if cfg!(feature = "trace") {
+            $crate::trace::record(
+                $crate::trace::Phase::$phase,
+                $crate::trace::Event::$event,
+                [$($arg as u64),*],
+            );
+        }
+    };
+}
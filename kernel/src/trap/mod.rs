@@ -0,0 +1,105 @@
+use riscv::register::{scause, sstatus, stval};
+
+use crate::hart;
+use crate::ipi;
+use crate::ksyms::Sym;
+use crate::mm;
+use crate::percpu;
+use crate::pr_err;
+use crate::timer;
+use crate::trace;
+
+/*
+ 内核态陷入处理
+
+ 入口在 entry.S, 只保存调用者保存的寄存器, 被调用者保存的寄存器由 Rust 代码负责
+ 目前只处理时钟中断和 IPI, 其余陷入都视为内核错误
+
+ Also see:
+ Glenda/kernel/src/trap/entry.S
+*/
+
+// 关闭本 hart 的中断, 返回之前是否开启
+pub fn local_irq_save() -> bool {
+    let enabled = sstatus::read().sie();
+    unsafe { sstatus::clear_sie() };
+    enabled
+}
+
+pub fn local_irq_restore(enabled: bool) {
+    if enabled {
+        unsafe { sstatus::set_sie() };
+    }
+}
+
+// scause 中的中断编号
+const IRQ_S_SOFT: usize = 1;
+const IRQ_S_TIMER: usize = 5;
+
+// 字段顺序与 entry.S 中的保存顺序一致
+#[repr(C)]
+#[derive(Debug)]
+pub struct TrapFrame {
+    pub ra: usize,
+    pub t: [usize; 7],
+    pub a: [usize; 8],
+    pub sepc: usize,
+    pub sstatus: usize,
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn kernel_trap(frame: &mut TrapFrame) {
+    percpu::irq_enter();
+    let scause = scause::read();
+    let stval = stval::read();
+    trace!(begin Trap, scause.bits());
+
+    if scause.is_interrupt() && scause.code() == IRQ_S_TIMER {
+        timer::tick(frame.sepc);
+        trace!(end Trap);
+        percpu::irq_exit();
+        return;
+    }
+    if scause.is_interrupt() && scause.code() == IRQ_S_SOFT {
+        ipi::handle(frame);
+        trace!(end Trap);
+        percpu::irq_exit();
+        return;
+    }
+
+    // 栈溢出通常在入口处就被截获, 这里处理越过入口检查的情况 (例如一次性分配了很大的栈帧)
+    if let Some(owner) = mm::stack::stack_guard_owner(stval) {
+        pr_err!("kernel stack guard page of hart {} hit at 0x{:x}", owner, stval);
+    }
+
+    panic!(
+        "unhandled trap on hart {}: scause=0x{:x}, sepc=0x{:x} ({}), stval=0x{:x}",
+        hart::current_id(),
+        scause.bits(),
+        frame.sepc,
+        Sym::pc(frame.sepc),
+        stval
+    );
+}
+
+// 由 entry.S 在应急栈上调用, sp 为溢出时的栈指针
+#[unsafe(no_mangle)]
+extern "C" fn kernel_stack_overflow(sp: usize) -> ! {
+    let hartid = hart::current_id();
+    let (bottom, top) = mm::stack::kernel_stack(hartid);
+    let sepc = riscv::register::sepc::read();
+    pr_err!(
+        "kernel stack overflow on hart {}: sp=0x{:x}, stack 0x{:x}-0x{:x}",
+        hartid,
+        sp,
+        bottom,
+        top
+    );
+    panic!(
+        "kernel stack overflow on hart {} (sepc=0x{:x} ({}), stval=0x{:x})",
+        hartid,
+        sepc,
+        Sym::pc(sepc),
+        stval::read()
+    );
+}
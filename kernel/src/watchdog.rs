@@ -0,0 +1,133 @@
+#![allow(dead_code)]
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::hart::{self, MAX_HARTS};
+use crate::ksyms::Sym;
+use crate::panic;
+use crate::pr_err;
+use crate::timer;
+
+/*
+ 软死锁 (soft lockup) 检测
+
+ 每个 hart 记录两个时间:
+   - last_tick: 最近一次时钟中断, 长时间不更新说明该 hart 关着中断卡住了,
+     由其它 hart 在自己的时钟中断中发现
+   - last_touch: 最近一次调用 touch(), 调度点和空闲循环会调用它,
+     长时间不更新说明该 hart 开着中断卡在内核里, 由它自己的时钟中断发现
+
+ 超过 LOCKUP_THRESHOLD_MS 时报告一次, 恢复之后重新计时
+
+ Also see:
+ Glenda/kernel/src/timer.rs
+ Glenda/kernel/src/lock/wait.rs
+*/
+const LOCKUP_THRESHOLD_MS: usize = 10_000;
+
+struct HartWatch {
+    // 时钟中断已在该 hart 上启用
+    armed: AtomicBool,
+    last_tick: AtomicUsize,
+    last_touch: AtomicUsize,
+    // 最近一次时钟中断打断的位置, panic 时报告不响应的 hart 用
+    last_pc: AtomicUsize,
+    ticks: AtomicUsize,
+    soft_reported: AtomicBool,
+    stall_reported: AtomicBool,
+}
+
+impl HartWatch {
+    const fn new() -> Self {
+        Self {
+            armed: AtomicBool::new(false),
+            last_tick: AtomicUsize::new(0),
+            last_touch: AtomicUsize::new(0),
+            last_pc: AtomicUsize::new(0),
+            ticks: AtomicUsize::new(0),
+            soft_reported: AtomicBool::new(false),
+            stall_reported: AtomicBool::new(false),
+        }
+    }
+}
+
+static WATCH: [HartWatch; MAX_HARTS] = [const { HartWatch::new() }; MAX_HARTS];
+
+fn this_hart() -> &'static HartWatch {
+    &WATCH[hart::current_id()]
+}
+
+pub fn arm() {
+    let watch = this_hart();
+    let now = timer::now();
+    watch.last_tick.store(now, Ordering::Relaxed);
+    watch.last_touch.store(now, Ordering::Relaxed);
+    watch.armed.store(true, Ordering::Release);
+}
+
+// 本 hart 正常运行的证明, 由调度点和空闲循环调用
+pub fn touch() {
+    let watch = this_hart();
+    watch.last_touch.store(timer::now(), Ordering::Relaxed);
+    watch.soft_reported.store(false, Ordering::Relaxed);
+}
+
+// 本 hart 收到的时钟中断次数
+pub fn ticks() -> usize {
+    this_hart().ticks.load(Ordering::Relaxed)
+}
+
+// 某个 hart 最近一次时钟中断时的 pc, 以及距今的毫秒数
+pub fn last_pc(hartid: usize) -> Option<(usize, usize)> {
+    let watch = WATCH.get(hartid).filter(|watch| watch.armed.load(Ordering::Acquire))?;
+    let ago = timer::now().wrapping_sub(watch.last_tick.load(Ordering::Relaxed));
+    Some((watch.last_pc.load(Ordering::Relaxed), timer::ticks_to_ms(ago)))
+}
+
+pub fn tick(sepc: usize) {
+    let hartid = hart::current_id();
+    let watch = this_hart();
+    let now = timer::now();
+    let threshold = timer::ms_to_ticks(LOCKUP_THRESHOLD_MS);
+    watch.last_tick.store(now, Ordering::Relaxed);
+    watch.last_pc.store(sepc, Ordering::Relaxed);
+    watch.ticks.fetch_add(1, Ordering::Relaxed);
+    watch.stall_reported.store(false, Ordering::Relaxed);
+
+    // panic 时其它 hart 本来就会停下, 不再报告
+    if panic::in_progress() {
+        return;
+    }
+
+    let stuck = now.wrapping_sub(watch.last_touch.load(Ordering::Relaxed));
+    if stuck > threshold && !watch.soft_reported.swap(true, Ordering::Relaxed) {
+        pr_err!(
+            "soft lockup on hart {}: stuck for {} ms at sepc=0x{:x} ({})",
+            hartid,
+            timer::ticks_to_ms(stuck),
+            sepc,
+            Sym::pc(sepc)
+        );
+    }
+
+    for other in (0..hart::cpu_count()).filter_map(hart::hart_id) {
+        let Some(peer) = WATCH.get(other) else {
+            continue;
+        };
+        if other == hartid || !peer.armed.load(Ordering::Acquire) {
+            continue;
+        }
+        let silent = now.wrapping_sub(peer.last_tick.load(Ordering::Relaxed));
+        // 时间可能在读取之后被对方更新, 只看明显落后的情况
+        if silent > threshold
+            && silent < usize::MAX / 2
+            && !peer.stall_reported.swap(true, Ordering::Relaxed)
+        {
+            pr_err!(
+                "hart {} has not ticked for {} ms (interrupts disabled?)",
+                other,
+                timer::ticks_to_ms(silent)
+            );
+        }
+    }
+}
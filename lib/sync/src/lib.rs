@@ -0,0 +1,11 @@
+// 内核与驱动共用的同步原语
+
+#![no_std]
+
+#[cfg(any(test, loom))]
+extern crate std;
+
+mod once;
+mod primitives;
+
+pub use once::{Lazy, OnceCell};
@@ -0,0 +1,292 @@
+use core::convert::Infallible;
+use core::mem::{self, MaybeUninit};
+use core::ops::Deref;
+
+use crate::primitives::{AtomicU8, Ordering, UnsafeCell, spin_loop};
+
+/*
+ 只初始化一次的单元
+
+ 多个 hart 同时调用 get_or_init 时只有一个执行初始化, 其余自旋等待它完成:
+
+   INCOMPLETE --(抢到初始化)--> RUNNING --(成功)--> COMPLETE
+                                        |--(返回错误)--> INCOMPLETE, 之后的调用者重试
+                                        `--(panic)--> POISONED, 之后的调用者 panic
+
+ panic 只有在展开 (unwind) 时才会把单元标记为 POISONED; 内核以 panic=abort 构建,
+ 出错的 hart 直接停下, 等待者会一直自旋
+
+ Also see:
+ Glenda/kernel/src/dtb.rs
+ Glenda/lib/sync/tests/loom.rs
+*/
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+const POISONED: u8 = 3;
+
+pub struct OnceCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    #[cfg(not(loom))]
+    pub const fn new() -> Self {
+        Self { state: AtomicU8::new(INCOMPLETE), value: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+
+    // loom 的原子变量不能在 const 上下文中构造
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        Self { state: AtomicU8::new(INCOMPLETE), value: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            Some(self.value.with_mut(|slot| unsafe { (*slot).assume_init_mut() }))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Acquire) == POISONED
+    }
+
+    // 已经初始化时把 value 原样还回
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+        match self.get_or_try_init(|| Ok::<T, Infallible>(init())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /*
+     init 返回错误时单元回到未初始化状态并把错误交给调用者,
+     正在等待的其它调用者会用自己的 init 重试
+    */
+    pub fn get_or_try_init<E>(&self, init: impl FnOnce() -> Result<T, E>) -> Result<&T, E> {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        loop {
+            match self.state.compare_exchange(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(COMPLETE) => return Ok(unsafe { self.get_unchecked() }),
+                Err(POISONED) => panic!("OnceCell poisoned by a panic during initialization"),
+                Err(_) => spin_loop(),
+            }
+        }
+
+        let guard = PoisonOnPanic { state: &self.state };
+        match init() {
+            Ok(value) => {
+                self.value.with_mut(|slot| unsafe { (*slot).write(value) });
+                guard.finish(COMPLETE);
+                Ok(unsafe { self.get_unchecked() })
+            }
+            Err(err) => {
+                guard.finish(INCOMPLETE);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn take(&mut self) -> Option<T> {
+        if self.state.load(Ordering::Acquire) != COMPLETE {
+            return None;
+        }
+        self.state.store(INCOMPLETE, Ordering::Relaxed);
+        Some(self.value.with(|slot| unsafe { (*slot).assume_init_read() }))
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+
+    // 调用者保证状态为 COMPLETE
+    unsafe fn get_unchecked(&self) -> &T {
+        self.value.with(|slot| unsafe { (*slot).assume_init_ref() })
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            self.value.with_mut(|slot| unsafe { (*slot).assume_init_drop() });
+        }
+    }
+}
+
+// 初始化函数 panic 时在展开过程中把单元标记为 POISONED
+struct PoisonOnPanic<'a> {
+    state: &'a AtomicU8,
+}
+
+impl PoisonOnPanic<'_> {
+    fn finish(self, state: u8) {
+        self.state.store(state, Ordering::Release);
+        mem::forget(self);
+    }
+}
+
+impl Drop for PoisonOnPanic<'_> {
+    fn drop(&mut self) {
+        self.state.store(POISONED, Ordering::Release);
+    }
+}
+
+/*
+ 第一次解引用时才计算的值:
+
+   static TABLE: Lazy<Table> = Lazy::new(Table::build);
+*/
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    // 只由抢到初始化的一方取出
+    init: UnsafeCell<Option<F>>,
+}
+
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    #[cfg(not(loom))]
+    pub const fn new(init: F) -> Self {
+        Self { cell: OnceCell::new(), init: UnsafeCell::new(Some(init)) }
+    }
+
+    #[cfg(loom)]
+    pub fn new(init: F) -> Self {
+        Self { cell: OnceCell::new(), init: UnsafeCell::new(Some(init)) }
+    }
+
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| match this.init.with_mut(|init| unsafe { (*init).take() }) {
+            Some(init) => init(),
+            None => panic!("Lazy poisoned by a panic during initialization"),
+        })
+    }
+
+    pub fn get(this: &Self) -> Option<&T> {
+        this.cell.get()
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn init_runs_once() {
+        static CELL: OnceCell<usize> = OnceCell::new();
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                thread::spawn(move || {
+                    *CELL.get_or_init(|| {
+                        CALLS.fetch_add(1, Ordering::Relaxed);
+                        i
+                    })
+                })
+            })
+            .collect();
+        let seen: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        assert!(seen.iter().all(|&v| v == seen[0]));
+    }
+
+    #[test]
+    fn error_allows_retry() {
+        let cell = OnceCell::new();
+        assert_eq!(cell.get_or_try_init(|| Err::<u32, _>("busy")), Err("busy"));
+        assert!(cell.get().is_none());
+        assert_eq!(cell.get_or_try_init(|| Ok::<_, ()>(7)), Ok(&7));
+        assert_eq!(cell.get_or_try_init(|| Err(())), Ok(&7));
+    }
+
+    #[test]
+    fn panic_poisons() {
+        let cell = OnceCell::<u32>::new();
+        let result = catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!("boom"))));
+        assert!(result.is_err());
+        assert!(cell.is_poisoned());
+        assert!(catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| 1))).is_err());
+    }
+
+    #[test]
+    fn set_and_take() {
+        let mut cell = OnceCell::new();
+        assert_eq!(cell.set(1), Ok(()));
+        assert_eq!(cell.set(2), Err(2));
+        assert_eq!(cell.take(), Some(1));
+        assert_eq!(cell.get(), None);
+    }
+
+    #[test]
+    fn value_dropped_once() {
+        let value = Arc::new(());
+        let cell = OnceCell::new();
+        cell.set(value.clone()).unwrap();
+        assert_eq!(Arc::strong_count(&value), 2);
+        drop(cell);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn lazy_is_lazy() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: Lazy<usize> = Lazy::new(|| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            42
+        });
+        assert!(Lazy::get(&VALUE).is_none());
+        assert_eq!(*VALUE, 42);
+        assert_eq!(*VALUE, 42);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,47 @@
+/*
+ 底层原语的切换层
+
+ 以 RUSTFLAGS="--cfg loom" 构建时换成 loom 的实现, 由 loom 枚举线程交错检查并发错误;
+ 平时使用 core 的实现, UnsafeCell 包装成与 loom 相同的 with/with_mut 接口
+
+ Also see:
+ Glenda/lib/sync/tests/loom.rs
+*/
+
+#[cfg(loom)]
+pub(crate) use loom::cell::UnsafeCell;
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicU8, Ordering};
+
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic::{AtomicU8, Ordering};
+
+#[cfg(not(loom))]
+#[derive(Debug)]
+pub(crate) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    pub(crate) const fn new(data: T) -> Self {
+        Self(core::cell::UnsafeCell::new(data))
+    }
+
+    pub(crate) fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    pub(crate) fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+// 等待其它线程完成初始化; loom 中必须主动让出, 否则模型不会前进
+#[cfg(loom)]
+pub(crate) fn spin_loop() {
+    loom::thread::yield_now();
+}
+
+#[cfg(not(loom))]
+pub(crate) fn spin_loop() {
+    core::hint::spin_loop();
+}
@@ -0,0 +1,96 @@
+/*
+ OnceCell/Lazy 初始化竞争的 loom 模型测试, loom 会枚举所有线程交错:
+
+   cargo xtask loom   (即 RUSTFLAGS="--cfg loom" cargo test -p sync --test loom --release)
+*/
+#![cfg(loom)]
+
+use loom::sync::Arc;
+use loom::sync::atomic::{AtomicUsize, Ordering};
+use loom::thread;
+
+use sync::{Lazy, OnceCell};
+
+// 两个线程竞争初始化, 只有一个初始化函数运行, 两者看到同一个值
+#[test]
+fn racing_init_runs_once() {
+    loom::model(|| {
+        let cell = Arc::new(OnceCell::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                let cell = cell.clone();
+                let calls = calls.clone();
+                thread::spawn(move || {
+                    *cell.get_or_init(|| {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                        i
+                    })
+                })
+            })
+            .collect();
+        let seen: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(seen[0], seen[1]);
+        assert_eq!(cell.get(), Some(&seen[0]));
+    });
+}
+
+// 第一个初始化失败后, 另一个线程可以重试成功; 失败方之后也能看到结果
+#[test]
+fn failed_init_is_retried() {
+    loom::model(|| {
+        let cell = Arc::new(OnceCell::new());
+
+        let failing = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.get_or_try_init(|| Err::<usize, _>(())).ok().copied())
+        };
+        let succeeding = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.get_or_try_init(|| Ok::<_, ()>(7)).ok().copied())
+        };
+
+        let failed = failing.join().unwrap();
+        assert_eq!(succeeding.join().unwrap(), Some(7));
+        // 失败的一方要么在成功之前返回错误, 要么直接看到成功的值
+        assert!(failed.is_none() || failed == Some(7));
+        assert_eq!(cell.get(), Some(&7));
+    });
+}
+
+// get 只会看到完整初始化的值
+#[test]
+fn get_sees_complete_value() {
+    loom::model(|| {
+        let cell = Arc::new(OnceCell::new());
+
+        let writer = {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let _ = cell.set((1usize, 2usize));
+            })
+        };
+        if let Some(&(a, b)) = cell.get() {
+            assert_eq!((a, b), (1, 2));
+        }
+        writer.join().unwrap();
+        assert_eq!(cell.get(), Some(&(1, 2)));
+    });
+}
+
+#[test]
+fn lazy_forced_concurrently() {
+    loom::model(|| {
+        let lazy = Arc::new(Lazy::new(|| 5usize));
+
+        let other = {
+            let lazy = lazy.clone();
+            thread::spawn(move || **lazy)
+        };
+        assert_eq!(**lazy, 5);
+        assert_eq!(other.join().unwrap(), 5);
+    });
+}
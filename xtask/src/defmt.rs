@@ -0,0 +1,485 @@
+//! Decodes the binary log frames of a kernel built with the `deferred-log` feature.
+//!
+//! Format strings live in the kernel's non-loaded `.glenda_log` section; the serial stream carries
+//! plain text interleaved with frames that reference them by id. See
+//! `kernel/src/printk/deferred.rs` for both layouts.
+
+use object::{Object, ObjectSection};
+use std::collections::HashMap;
+use std::path::Path;
+
+const FRAME_START: u8 = 0xff;
+
+const TAG_UNSIGNED: u8 = 0;
+const TAG_SIGNED: u8 = 1;
+const TAG_BOOL: u8 = 2;
+const TAG_CHAR: u8 = 3;
+const TAG_STR: u8 = 4;
+const TAG_PTR: u8 = 5;
+const TAG_PREFORMATTED: u8 = 6;
+const TAG_LOCATION: u8 = 7;
+
+const LEVELS: [(&str, &str); 4] =
+    [("\x1b[31m", "\x1b[0m"), ("\x1b[33m", "\x1b[0m"), ("", ""), ("\x1b[36m", "\x1b[0m")];
+
+struct Record {
+    level: u8,
+    module: String,
+    format: String,
+}
+
+/// Reads the format string records from the kernel ELF.
+fn records(elf: &Path) -> anyhow::Result<HashMap<u32, Record>> {
+    let data = std::fs::read(elf)?;
+    let file = object::File::parse(&*data)?;
+    let section = file.section_by_name(".glenda_log").ok_or_else(|| {
+        anyhow::anyhow!(
+            "[ ERROR ] {} has no .glenda_log section; build with --features deferred-log",
+            elf.display()
+        )
+    })?;
+    parse_records(section.data()?)
+}
+
+fn parse_records(section: &[u8]) -> anyhow::Result<HashMap<u32, Record>> {
+    let mut reader = Reader(section);
+    let mut records = HashMap::new();
+    while !reader.0.is_empty() {
+        let bad = || anyhow::anyhow!("[ ERROR ] truncated .glenda_log section");
+        let id = u32::from_le_bytes(reader.take(4).ok_or_else(bad)?.try_into().unwrap());
+        let level = reader.take(1).ok_or_else(bad)?[0];
+        let module = reader.str().ok_or_else(bad)?;
+        let format = reader.str().ok_or_else(bad)?;
+        if let Some(other) = records.get(&id).filter(|other: &&Record| other.format != format) {
+            eprintln!("[ WARN ] id {:#x} is shared by {:?} and {:?}", id, other.format, format);
+        }
+        records.insert(id, Record { level, module, format });
+    }
+    Ok(records)
+}
+
+pub fn decode(elf: &Path, log: &[u8]) -> anyhow::Result<String> {
+    Ok(decode_stream(&records(elf)?, log))
+}
+
+fn decode_stream(records: &HashMap<u32, Record>, log: &[u8]) -> String {
+    // The UART driver turns every \n into \r\n, including bytes inside frames; undo that first
+    let mut stream = Vec::with_capacity(log.len());
+    for &byte in log {
+        if byte == b'\n' && stream.last() == Some(&b'\r') {
+            stream.pop();
+        }
+        stream.push(byte);
+    }
+
+    let mut out = String::new();
+    let mut rest = &stream[..];
+    while let Some(start) = rest.iter().position(|&byte| byte == FRAME_START) {
+        out.push_str(&String::from_utf8_lossy(&rest[..start]));
+        rest = &rest[start + 1..];
+        // A capture cut off mid-frame; the partial frame is not text
+        let Some(payload) = rest
+            .get(..2)
+            .map(|len| u16::from_le_bytes([len[0], len[1]]) as usize)
+            .and_then(|len| rest.get(2..2 + len))
+        else {
+            out.push_str("<truncated log frame>\n");
+            rest = &[];
+            break;
+        };
+        rest = &rest[2 + payload.len()..];
+        match frame(records, payload) {
+            Some(line) => out.push_str(&line),
+            None => out.push_str("<malformed log frame>\n"),
+        }
+    }
+    out.push_str(&String::from_utf8_lossy(rest));
+    out
+}
+
+// Rebuilds the line printk::_log would have printed
+fn frame(records: &HashMap<u32, Record>, payload: &[u8]) -> Option<String> {
+    let mut reader = Reader(payload);
+    let id = u32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+    let micros = reader.varint()?;
+    let hart = reader.varint()?;
+    let Some(record) = records.get(&id) else {
+        return Some(format!("<unknown log id {:#x}; does the ELF match the kernel?>\n", id));
+    };
+    let mut args = Vec::new();
+    while !reader.0.is_empty() {
+        args.push(Arg::read(&mut reader)?);
+    }
+    let tag = record.module.split_once("::").map_or(record.module.as_str(), |(_, tag)| tag);
+    let (color, reset) = LEVELS.get(record.level as usize).copied().unwrap_or(("", ""));
+    Some(format!(
+        "{}[{:>5}.{:06}] hart {} {}: {}{}\n",
+        color,
+        micros / 1_000_000,
+        micros % 1_000_000,
+        hart,
+        tag,
+        render(&record.format, &args),
+        reset
+    ))
+}
+
+enum Arg {
+    Unsigned(u64),
+    Signed(i64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Ptr(u64),
+    Preformatted(String),
+    Location(String, u64, u64),
+}
+
+impl Arg {
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(match reader.take(1)?[0] {
+            TAG_UNSIGNED => Arg::Unsigned(reader.varint()?),
+            TAG_SIGNED => {
+                let value = reader.varint()?;
+                Arg::Signed((value >> 1) as i64 ^ -((value & 1) as i64))
+            }
+            TAG_BOOL => Arg::Bool(reader.take(1)?[0] != 0),
+            TAG_CHAR => Arg::Char(char::from_u32(reader.varint()? as u32)?),
+            TAG_STR => Arg::Str(reader.str()?),
+            TAG_PTR => Arg::Ptr(reader.varint()?),
+            TAG_PREFORMATTED => Arg::Preformatted(reader.str()?),
+            TAG_LOCATION => Arg::Location(reader.str()?, reader.varint()?, reader.varint()?),
+            _ => return None,
+        })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.0.get(..len)?;
+        self.0 = &self.0[len..];
+        Some(bytes)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.take(2)?;
+        let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+        Some(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+/// Substitutes `args` into a Rust format string, supporting the positional `{}` / `{:spec}` forms.
+fn render(format: &str, args: &[Arg]) -> String {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let placeholder: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let spec = placeholder.split_once(':').map_or("", |(_, spec)| spec);
+                match args.next() {
+                    Some(arg) => out.push_str(&Spec::parse(spec).apply(arg)),
+                    None => out.push_str("<?>"),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `[[fill]align][+]['#']['0'][width]['.' precision][type]`
+#[derive(Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<char>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: String,
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Self {
+        let mut result = Spec::default();
+        let chars: Vec<char> = spec.chars().collect();
+        let mut i = 0;
+        let is_align = |c: Option<&char>| matches!(c, Some('<' | '^' | '>'));
+        if is_align(chars.get(1)) {
+            result.fill = Some(chars[0]);
+            result.align = Some(chars[1]);
+            i = 2;
+        } else if is_align(chars.first()) {
+            result.align = Some(chars[0]);
+            i = 1;
+        }
+        if chars.get(i) == Some(&'+') {
+            result.plus = true;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'#') {
+            result.alternate = true;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'0') {
+            result.zero = true;
+            i += 1;
+        }
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while chars.get(*i).is_some_and(char::is_ascii_digit) {
+                *i += 1;
+            }
+            chars[start..*i].iter().collect::<String>().parse().ok()
+        };
+        result.width = digits(&mut i).unwrap_or(0);
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            result.precision = digits(&mut i);
+        }
+        result.kind = chars[i..].iter().collect();
+        result
+    }
+
+    fn apply(&self, arg: &Arg) -> String {
+        let debug = self.kind == "?";
+        // (sign, prefix, digits) for numbers so zero padding goes between prefix and digits
+        let (sign, body, numeric) = match arg {
+            Arg::Unsigned(value) => ("", self.integer(*value), true),
+            Arg::Signed(value) => {
+                let sign = if *value < 0 {
+                    "-"
+                } else if self.plus {
+                    "+"
+                } else {
+                    ""
+                };
+                (sign, self.integer(value.unsigned_abs()), true)
+            }
+            Arg::Ptr(value) => ("", format!("{:#x}", value), true),
+            Arg::Bool(value) => ("", value.to_string(), false),
+            Arg::Char(value) if debug => ("", format!("{:?}", value), false),
+            Arg::Char(value) => ("", value.to_string(), false),
+            Arg::Str(value) if debug => ("", format!("{:?}", value), false),
+            Arg::Str(value) => {
+                let truncated = match self.precision {
+                    Some(precision) => value.chars().take(precision).collect(),
+                    None => value.clone(),
+                };
+                ("", truncated, false)
+            }
+            Arg::Preformatted(value) => ("", value.clone(), false),
+            Arg::Location(file, line, column) => {
+                ("", format!("{}:{}:{}", file, line, column), false)
+            }
+        };
+
+        let len = sign.chars().count() + body.chars().count();
+        if len >= self.width {
+            return format!("{}{}", sign, body);
+        }
+        let pad = self.width - len;
+        if numeric && self.zero && self.align.is_none() {
+            let split =
+                if body.starts_with("0x") || body.starts_with("0o") || body.starts_with("0b") {
+                    2
+                } else {
+                    0
+                };
+            return format!("{}{}{}{}", sign, &body[..split], "0".repeat(pad), &body[split..]);
+        }
+        let fill = self.fill.unwrap_or(' ').to_string();
+        let align = self.align.unwrap_or(if numeric { '>' } else { '<' });
+        let (left, right) = match align {
+            '<' => (0, pad),
+            '^' => (pad / 2, pad - pad / 2),
+            _ => (pad, 0),
+        };
+        format!("{}{}{}{}", fill.repeat(left), sign, body, fill.repeat(right))
+    }
+
+    fn integer(&self, value: u64) -> String {
+        match (self.kind.as_str(), self.alternate) {
+            ("x", false) => format!("{:x}", value),
+            ("x", true) => format!("{:#x}", value),
+            ("X", false) => format!("{:X}", value),
+            ("X", true) => format!("{:#X}", value),
+            ("o", false) => format!("{:o}", value),
+            ("o", true) => format!("{:#o}", value),
+            ("b", false) => format!("{:b}", value),
+            ("b", true) => format!("{:#b}", value),
+            _ => value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u32 = 0x1234_5678;
+
+    // One .glenda_log record, laid out like `record()` in kernel/src/printk/deferred.rs
+    fn record(id: u32, level: u8, module: &str, format: &str) -> Vec<u8> {
+        let mut bytes = id.to_le_bytes().to_vec();
+        bytes.push(level);
+        for part in [module, format] {
+            bytes.extend_from_slice(&(part.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(part.as_bytes());
+        }
+        bytes
+    }
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        loop {
+            let byte = value as u8 & 0x7f;
+            value >>= 7;
+            if value == 0 {
+                return out.push(byte);
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn frame(id: u32, micros: u64, hart: u64, args: &[u8]) -> Vec<u8> {
+        let mut payload = id.to_le_bytes().to_vec();
+        varint(micros, &mut payload);
+        varint(hart, &mut payload);
+        payload.extend_from_slice(args);
+        let mut bytes = vec![FRAME_START];
+        bytes.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    fn unsigned(value: u64) -> Vec<u8> {
+        let mut bytes = vec![TAG_UNSIGNED];
+        varint(value, &mut bytes);
+        bytes
+    }
+
+    fn signed(value: i64) -> Vec<u8> {
+        let mut bytes = vec![TAG_SIGNED];
+        varint(((value << 1) ^ (value >> 63)) as u64, &mut bytes);
+        bytes
+    }
+
+    // What the UART driver puts on the wire
+    fn uart(bytes: &[u8]) -> Vec<u8> {
+        bytes
+            .iter()
+            .flat_map(|&byte| if byte == b'\n' { vec![b'\r', b'\n'] } else { vec![byte] })
+            .collect()
+    }
+
+    fn records(format: &str) -> HashMap<u32, Record> {
+        parse_records(&record(ID, 2, "kernel::mm", format)).unwrap()
+    }
+
+    #[test]
+    fn parses_records() {
+        let mut section = record(1, 0, "kernel::panic", "a {}");
+        section.extend(record(2, 3, "kernel", "b"));
+        let records = parse_records(&section).unwrap();
+        assert_eq!(records[&1].level, 0);
+        assert_eq!(records[&1].module, "kernel::panic");
+        assert_eq!(records[&2].format, "b");
+        assert!(parse_records(&section[..section.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn decodes_frames_between_text() {
+        let mut log = b"OpenSBI\n".to_vec();
+        log.extend(frame(ID, 1_000_002, 3, &unsigned(42)));
+        log.extend(b"plain printk\n");
+        let out = decode_stream(&records("value {}"), &uart(&log));
+        assert_eq!(out, "OpenSBI\n[    1.000002] hart 3 mm: value 42\nplain printk\n");
+    }
+
+    #[test]
+    fn undoes_crlf_inside_frames() {
+        // The timestamp, the first argument and the high byte of the second are all 0x0a
+        let args: Vec<u8> = [unsigned(10), unsigned(0x500)].concat();
+        let frame = frame(ID, 10, 0, &args);
+        assert_eq!(frame.iter().filter(|&&byte| byte == b'\n').count(), 3);
+        let log = uart(&frame);
+        assert_eq!(log.len(), frame.len() + 3);
+        let out = decode_stream(&records("{} {:#x}"), &log);
+        assert_eq!(out, "[    0.000010] hart 0 mm: 10 0x500\n");
+    }
+
+    #[test]
+    fn zigzag_signed_values() {
+        let args: Vec<u8> =
+            [signed(-1), signed(1), signed(-300), signed(i64::MIN), signed(i64::MAX)].concat();
+        let out = decode_stream(&records("{} {} {} {} {:+}"), &frame(ID, 0, 0, &args));
+        assert!(out.ends_with(&format!(": -1 1 -300 {} +{}\n", i64::MIN, i64::MAX)), "{}", out);
+    }
+
+    #[test]
+    fn truncated_and_malformed_frames() {
+        let mut log = b"before\n".to_vec();
+        log.extend(&frame(ID, 0, 0, &unsigned(1))[..6]);
+        assert_eq!(decode_stream(&records("{}"), &log), "before\n<truncated log frame>\n");
+
+        let out = decode_stream(&records("{}"), &[b'x', FRAME_START, 1]);
+        assert_eq!(out, "x<truncated log frame>\n");
+
+        let out = decode_stream(&records("{}"), &frame(ID, 0, 0, &[0x7f]));
+        assert_eq!(out, "<malformed log frame>\n");
+        let out = decode_stream(&records("{}"), &frame(ID + 1, 0, 0, &[]));
+        assert!(out.starts_with("<unknown log id 0x12345679"));
+    }
+
+    #[test]
+    fn missing_arguments_render_as_placeholders() {
+        let out = decode_stream(&records("{} and {}, {{literal}}"), &frame(ID, 0, 0, &unsigned(1)));
+        assert!(out.ends_with(": 1 and <?>, {literal}\n"), "{}", out);
+    }
+
+    #[test]
+    fn format_specs() {
+        let apply = |spec: &str, arg: Arg| Spec::parse(spec).apply(&arg);
+        assert_eq!(apply("#010x", Arg::Unsigned(0x1234)), "0x00001234");
+        assert_eq!(apply("08x", Arg::Unsigned(0xbeef)), "0000beef");
+        assert_eq!(apply("#X", Arg::Unsigned(255)), "0xFF");
+        assert_eq!(apply("#b", Arg::Unsigned(5)), "0b101");
+        assert_eq!(apply("o", Arg::Unsigned(8)), "10");
+        assert_eq!(apply("05", Arg::Signed(-42)), "-0042");
+        assert_eq!(apply(">6", Arg::Str("ab".into())), "    ab");
+        assert_eq!(apply("*^7", Arg::Str("ab".into())), "**ab***");
+        assert_eq!(apply("6", Arg::Unsigned(7)), "     7");
+        assert_eq!(apply("6", Arg::Bool(true)), "true  ");
+        assert_eq!(apply(".3", Arg::Str("abcdef".into())), "abc");
+        assert_eq!(apply("?", Arg::Str("a\"b".into())), "\"a\\\"b\"");
+        assert_eq!(apply("?", Arg::Char('x')), "'x'");
+        assert_eq!(apply("", Arg::Ptr(0x80200000)), "0x80200000");
+        assert_eq!(apply("", Arg::Location("src/main.rs".into(), 7, 5)), "src/main.rs:7:5");
+        assert_eq!(apply("5", Arg::Preformatted("abc".into())), "abc  ");
+    }
+}
@@ -0,0 +1,110 @@
+//! Embeds the kernel symbol table into the `.ksyms` section of a linked kernel.
+//!
+//! The kernel reserves `GLENDA_KSYMS_SIZE` zero-filled bytes for the section (see
+//! `kernel/src/ksyms.rs` for the format). `cargo xtask build` links twice when the reservation
+//! does not match the table: once to learn the function symbols, once with the section sized to
+//! fit them. The section follows `.text` and `.rodata`, so resizing it moves no function. The
+//! table is then written in place.
+
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"KSYM";
+// Reservations are rounded up so small code changes do not force a second link
+const RESERVE_ALIGN: usize = 4096;
+
+/// Size of the `.ksyms` section of an existing kernel, 0 when there is none.
+pub fn reserved(elf: &Path) -> u64 {
+    let Ok(data) = fs::read(elf) else {
+        return 0;
+    };
+    object::File::parse(&*data)
+        .ok()
+        .and_then(|file| file.section_by_name(".ksyms").map(|section| section.size()))
+        .unwrap_or(0)
+}
+
+/// Bytes the `.ksyms` section needs to hold the table of `elf`.
+pub fn required(elf: &Path) -> anyhow::Result<u64> {
+    let data = fs::read(elf)?;
+    let blob = table(&object::File::parse(&*data)?)?.0;
+    Ok(blob.len().next_multiple_of(RESERVE_ALIGN) as u64)
+}
+
+/// Writes the table into `elf` and returns the number of symbols embedded.
+pub fn embed(elf: &Path) -> anyhow::Result<usize> {
+    let mut data = fs::read(elf)?;
+    let (range, blob, count) = {
+        let file = object::File::parse(&*data)?;
+        let section = file
+            .section_by_name(".ksyms")
+            .ok_or_else(|| anyhow::anyhow!("[ ERROR ] no .ksyms section in {}", elf.display()))?;
+        let (offset, size) = section
+            .file_range()
+            .ok_or_else(|| anyhow::anyhow!("[ ERROR ] .ksyms has no file contents"))?;
+        let (blob, count) = table(&file)?;
+        if blob.len() as u64 > size {
+            return Err(anyhow::anyhow!(
+                "[ ERROR ] symbol table needs {} bytes but .ksyms has {}; rebuild with `cargo xtask build`",
+                blob.len(),
+                size
+            ));
+        }
+        (offset as usize..(offset + size) as usize, blob, count)
+    };
+
+    let section = &mut data[range];
+    section.fill(0);
+    section[..blob.len()].copy_from_slice(&blob);
+    fs::write(elf, data)?;
+    Ok(count)
+}
+
+// The encoded table and the number of symbols in it
+fn table(file: &object::File) -> anyhow::Result<(Vec<u8>, usize)> {
+    let base = file
+        .symbol_by_name("__kernel_start")
+        .ok_or_else(|| anyhow::anyhow!("[ ERROR ] __kernel_start not found"))?
+        .address();
+
+    let mut symbols: Vec<(u64, u64, String)> = file
+        .symbols()
+        .filter(|sym| sym.kind() == SymbolKind::Text && sym.is_definition() && sym.size() > 0)
+        .filter_map(|sym| {
+            let name = sym.name().ok()?;
+            Some((
+                sym.address() - base,
+                sym.size(),
+                format!("{:#}", rustc_demangle::demangle(name)),
+            ))
+        })
+        .collect();
+    symbols.sort();
+    // Aliases share an address; keep one name per function
+    symbols.dedup_by_key(|sym| sym.0);
+    Ok((encode(&symbols)?, symbols.len()))
+}
+
+fn encode(symbols: &[(u64, u64, String)]) -> anyhow::Result<Vec<u8>> {
+    let mut entries = Vec::new();
+    let mut names = Vec::new();
+    for (addr, size, name) in symbols {
+        let name = &name.as_bytes()[..name.len().min(u16::MAX as usize)];
+        for field in [*addr, *size, names.len() as u64] {
+            let field = u32::try_from(field).map_err(|_| {
+                anyhow::anyhow!("[ ERROR ] symbol {} does not fit the table", name.escape_ascii())
+            })?;
+            entries.extend_from_slice(&field.to_le_bytes());
+        }
+        names.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        names.extend_from_slice(name);
+    }
+
+    let mut blob = Vec::with_capacity(8 + entries.len() + names.len());
+    blob.extend_from_slice(MAGIC);
+    blob.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    blob.extend_from_slice(&entries);
+    blob.extend_from_slice(&names);
+    Ok(blob)
+}
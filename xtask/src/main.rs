@@ -1,8 +1,14 @@
 use clap::{Parser, Subcommand};
+use std::fs;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use which::which;
 
+mod defmt;
+mod ksyms;
+mod trace;
+
 #[derive(Parser, Debug)]
 #[command(name = "xtask", version, about = "Glenda Build System")]
 struct Xtask {
@@ -48,6 +54,8 @@ enum Cmd {
         #[arg(long, default_value = "nographic")]
         display: String,
     },
+    /// Run the loom model tests of lib/sync on the host
+    Loom,
     /// Start QEMU paused and wait for GDB
     Gdb {
         /// Number of virtual CPUs to pass to QEMU
@@ -61,11 +69,42 @@ enum Cmd {
         /// Display device for QEMU. Use "nographic" for serial-only, or a display backend (e.g. "gtk", "sdl", "none").
         #[arg(long, default_value = "nographic")]
         display: String,
+
+        /// KASLR kernel offset printed by a debug kernel (e.g. 0x7c00000000). Without it the kernel boots with `nokaslr`
+        #[arg(long, value_parser = parse_hex)]
+        kaslr_offset: Option<u64>,
     },
     /// Disassemble the kernel ELF
     Objdump,
     /// Show section sizes
     Size,
+    /// Resolve the `[<address>]` frames of a kernel backtrace to function and file:line
+    Symbolize {
+        /// Serial log containing the backtrace; read from stdin when omitted
+        log: Option<PathBuf>,
+
+        /// Kernel ELF that produced the log (defaults to the one built for the current mode)
+        #[arg(long)]
+        elf: Option<PathBuf>,
+    },
+    /// Convert a trace dump from a serial log into a Chrome/Perfetto JSON timeline
+    Trace {
+        /// Serial log containing the `[trace]` dump; read from stdin when omitted
+        log: Option<PathBuf>,
+
+        /// Where to write the JSON (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Turn the serial output of a `deferred-log` kernel back into text
+    DecodeLog {
+        /// Raw serial capture; read from stdin when omitted
+        log: Option<PathBuf>,
+
+        /// Kernel ELF that produced the log (defaults to the one built for the current mode)
+        #[arg(long)]
+        elf: Option<PathBuf>,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
@@ -78,16 +117,29 @@ fn main() -> anyhow::Result<()> {
             build(mode, &xtask.features)?;
             qemu_run(mode, cpus, &mem, &display)?;
         }
-        Cmd::Gdb { cpus, mem, display } => {
+        Cmd::Gdb { cpus, mem, display, kaslr_offset } => {
             build(mode, &xtask.features)?;
-            qemu_gdb(mode, cpus, &mem, &display)?;
+            qemu_gdb(mode, cpus, &mem, &display, kaslr_offset)?;
         }
         Cmd::Test { cpus, mem, display } => {
             build(mode, &Vec::from([String::from("tests")]))?;
             qemu_run(mode, cpus, &mem, &display)?;
         }
+        Cmd::Loom => loom()?,
         Cmd::Objdump => objdump(mode)?,
         Cmd::Size => size(mode)?,
+        Cmd::Symbolize { log, elf } => symbolize(mode, log.as_deref(), elf.as_deref())?,
+        Cmd::Trace { log, output } => {
+            let json = trace::decode(&read_log(log.as_deref())?)?;
+            match output {
+                Some(path) => fs::write(path, json)?,
+                None => print!("{}", json),
+            }
+        }
+        Cmd::DecodeLog { log, elf } => {
+            let elf = elf.unwrap_or_else(|| elf_path(mode));
+            print!("{}", defmt::decode(&elf, &read_log_bytes(log.as_deref())?)?);
+        }
     }
     Ok(())
 }
@@ -96,7 +148,22 @@ fn elf_path(mode: &str) -> PathBuf {
     Path::new("target").join("riscv64gc-unknown-none-elf").join(mode).join("kernel")
 }
 
-fn build(mode: &str, features: &Vec<String>) -> anyhow::Result<()> {
+fn build(mode: &str, features: &[String]) -> anyhow::Result<()> {
+    let elf = elf_path(mode);
+    // Reuse the previous reservation so an unchanged kernel is not relinked
+    let reserved = ksyms::reserved(&elf);
+    cargo_build(mode, features, reserved)?;
+    let required = ksyms::required(&elf)?;
+    if required != reserved {
+        eprintln!("[ INFO ] Relinking with {} bytes reserved for kernel symbols", required);
+        cargo_build(mode, features, required)?;
+    }
+    let count = ksyms::embed(&elf)?;
+    eprintln!("[ INFO ] Embedded {} kernel symbols", count);
+    Ok(())
+}
+
+fn cargo_build(mode: &str, features: &[String], ksyms_size: u64) -> anyhow::Result<()> {
     let mut cmd = Command::new("cargo");
     cmd.arg("build").arg("-p").arg("kernel").arg("--target").arg("riscv64gc-unknown-none-elf");
     if mode == "release" {
@@ -106,6 +173,11 @@ fn build(mode: &str, features: &Vec<String>) -> anyhow::Result<()> {
         let joined = features.join(",");
         cmd.arg("--features").arg(joined);
     }
+    // Debug kernels keep frame pointers so panics can print a backtrace
+    if mode == "debug" {
+        cmd.env("CARGO_TARGET_RISCV64GC_UNKNOWN_NONE_ELF_RUSTFLAGS", "-Cforce-frame-pointers=yes");
+    }
+    cmd.env("GLENDA_KSYMS_SIZE", ksyms_size.to_string());
     run(&mut cmd)
 }
 
@@ -142,7 +214,13 @@ fn qemu_run(mode: &str, cpus: u32, mem: &str, display: &str) -> anyhow::Result<(
     run(&mut cmd)
 }
 
-fn qemu_gdb(mode: &str, cpus: u32, mem: &str, display: &str) -> anyhow::Result<()> {
+fn qemu_gdb(
+    mode: &str,
+    cpus: u32,
+    mem: &str,
+    display: &str,
+    kaslr_offset: Option<u64>,
+) -> anyhow::Result<()> {
     let elf = elf_path(mode);
     if !elf.exists() {
         return Err(anyhow::anyhow!("[ ERROR ] ELF not found: {}", elf.display()));
@@ -165,9 +243,17 @@ fn qemu_gdb(mode: &str, cpus: u32, mem: &str, display: &str) -> anyhow::Result<(
         cmd.arg("-display").arg(display);
     }
     cmd.arg("-bios").arg("default").arg("-S").arg("-s").arg("-kernel").arg(elf.to_str().unwrap());
+    // Symbols are linked at the non-randomized base; either relocate them or keep the kernel there
+    let symbol_file = match kaslr_offset {
+        Some(offset) => format!("symbol-file {} -o {:#x}", elf.display(), offset),
+        None => {
+            cmd.arg("-append").arg("nokaslr");
+            format!("symbol-file {}", elf.display())
+        }
+    };
     eprintln!("QEMU started. In another shell:");
     if which("gdb").is_ok() {
-        eprintln!("  gdb -ex 'set architecture riscv:rv64' -ex 'target remote :1234' -ex 'symbol-file {}'", elf.display());
+        eprintln!("  gdb -ex 'set architecture riscv:rv64' -ex 'target remote :1234' -ex '{}'", symbol_file);
     } else {
         eprintln!("[ ERROR ] install gdb or riscv64-unknown-elf-gdb first");
     }
@@ -180,11 +266,7 @@ fn objdump(mode: &str) -> anyhow::Result<()> {
         .or_else(|_| which("llvm-objdump"))
         .map_err(|_| anyhow::anyhow!("[ ERROR ] install objdump first"))?;
     let mut cmd = Command::new(tool);
-    if cmd.get_program().to_string_lossy().contains("llvm-objdump") {
-        cmd.args(["-d", "--all-headers", "--source", elf.to_str().unwrap()]);
-    } else {
-        cmd.args(["-d", "--all-headers", "--source", elf.to_str().unwrap()]);
-    }
+    cmd.args(["-d", "--all-headers", "--source", elf.to_str().unwrap()]);
     run(&mut cmd)
 }
 
@@ -198,6 +280,87 @@ fn size(mode: &str) -> anyhow::Result<()> {
     run(&mut cmd)
 }
 
+fn symbolize(mode: &str, log: Option<&Path>, elf: Option<&Path>) -> anyhow::Result<()> {
+    let elf = elf.map(Path::to_path_buf).unwrap_or_else(|| elf_path(mode));
+    if !elf.exists() {
+        return Err(anyhow::anyhow!("[ ERROR ] ELF not found: {}", elf.display()));
+    }
+    let tool = which("riscv64-unknown-elf-addr2line")
+        .or_else(|_| which("llvm-addr2line"))
+        .or_else(|_| which("addr2line"))
+        .map_err(|_| anyhow::anyhow!("[ ERROR ] install addr2line first"))?;
+    let text = read_log(log)?;
+
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        println!("{}", line);
+        let Some(lookup) = lookup_address(line) else {
+            continue;
+        };
+        let output = Command::new(&tool)
+            .args(["-f", "-C", "-i", "-e"])
+            .arg(&elf)
+            .arg(format!("{:#x}", lookup))
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "[ ERROR ] {} failed with status {}",
+                tool.display(),
+                output.status
+            ));
+        }
+        // addr2line prints a function line followed by its location, innermost inlined frame first
+        let resolved = String::from_utf8_lossy(&output.stdout);
+        let mut lines = resolved.lines();
+        while let (Some(function), Some(location)) = (lines.next(), lines.next()) {
+            println!("        {} at {}", function, location);
+        }
+    }
+    Ok(())
+}
+
+// Reads a serial log from `path`, or from stdin when none is given
+fn read_log(path: Option<&Path>) -> anyhow::Result<String> {
+    Ok(String::from_utf8_lossy(&read_log_bytes(path)?).into_owned())
+}
+
+fn read_log_bytes(path: Option<&Path>) -> anyhow::Result<Vec<u8>> {
+    match path {
+        Some(path) => Ok(fs::read(path)?),
+        None => {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+    }
+}
+
+// Extracts the address from a backtrace line such as `  #3  [<ffffffff80201234>]`
+fn frame_address(line: &str) -> Option<u64> {
+    let start = line.find("[<")? + 2;
+    let len = line[start..].find(">]")?;
+    u64::from_str_radix(&line[start..start + len], 16).ok()
+}
+
+// Return addresses point after the call; look up the call itself
+fn lookup_address(line: &str) -> Option<u64> {
+    let address = frame_address(line)?;
+    Some(if line.ends_with("(pc)") { address } else { address.saturating_sub(1) })
+}
+
+fn loom() -> anyhow::Result<()> {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["test", "-p", "sync", "--test", "loom", "--release"]);
+    // A separate target directory keeps `--cfg loom` from invalidating the regular host build
+    cmd.env("RUSTFLAGS", "--cfg loom").env("CARGO_TARGET_DIR", Path::new("target").join("loom"));
+    run(&mut cmd)
+}
+
+fn parse_hex(s: &str) -> Result<u64, String> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    u64::from_str_radix(digits, 16).map_err(|e| format!("invalid hex offset {s:?}: {e}"))
+}
+
 fn run(cmd: &mut Command) -> anyhow::Result<()> {
     eprintln!("[ INFO ] Running: $ {:?}", cmd);
     let status =
@@ -212,3 +375,30 @@ mod anyhow {
     pub use anyhow::*;
 }
 use anyhow::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_addresses() {
+        assert_eq!(frame_address("  #3  [<ffffffff80201234>]"), Some(0xffffffff80201234));
+        assert_eq!(
+            frame_address("  #0  [<ffffffff8020a000>] kernel::panic::handle+0x1c/0x200 (pc)"),
+            Some(0xffffffff8020a000)
+        );
+        assert_eq!(frame_address("Call trace (KASLR offset 0x7c00000000):"), None);
+        assert_eq!(frame_address("  #1  [<not-hex>]"), None);
+        assert_eq!(frame_address("  #1  [<ffffffff80201234"), None);
+    }
+
+    #[test]
+    fn lookup_addresses() {
+        assert_eq!(lookup_address("  #0  [<ffffffff80201000>] (pc)"), Some(0xffffffff80201000));
+        assert_eq!(
+            lookup_address("  #1  [<ffffffff80201000>] glenda_main+0x8/0x40"),
+            Some(0xffffffff80200fff)
+        );
+        assert_eq!(lookup_address("  #1  [<0>]"), Some(0));
+    }
+}
@@ -0,0 +1,183 @@
+//! Turns the `[trace]` lines printed by the kernel's `trace::dump()` into a Chrome/Perfetto JSON
+//! timeline (open it in https://ui.perfetto.dev or chrome://tracing).
+//!
+//! Dump format, one line each, words in hex:
+//!
+//! ```text
+//! [trace] begin <timebase-frequency>
+//! [trace] event <id> <name>
+//! [trace] lost <hartid> <count>
+//! [trace] <hartid> <timestamp> <id | phase << 16> <arg0> <arg1>
+//! [trace] end
+//! ```
+//!
+//! When the log holds several dumps, only the last one is used.
+
+use std::collections::HashMap;
+
+const PREFIX: &str = "[trace] ";
+
+#[derive(Default)]
+struct Dump {
+    timebase: u64,
+    names: HashMap<u64, String>,
+    records: Vec<(u64, [u64; 4])>,
+    lost: Vec<(u64, u64)>,
+}
+
+pub fn decode(log: &str) -> anyhow::Result<String> {
+    let mut dump = None;
+    let mut current: Option<Dump> = None;
+    for (number, line) in log.lines().enumerate() {
+        // Lines may carry a console prefix or a trailing carriage return
+        let Some(start) = line.find(PREFIX) else {
+            continue;
+        };
+        let fields: Vec<&str> = line[start + PREFIX.len()..].split_whitespace().collect();
+        let bad =
+            || anyhow::anyhow!("[ ERROR ] line {}: malformed trace record {:?}", number + 1, line);
+        match fields.as_slice() {
+            ["begin", timebase] => {
+                current =
+                    Some(Dump { timebase: timebase.parse().map_err(|_| bad())?, ..Dump::default() })
+            }
+            ["end"] => dump = current.take().or(dump),
+            fields => {
+                let Some(current) = current.as_mut() else {
+                    continue;
+                };
+                match fields {
+                    ["event", id, name] => {
+                        current.names.insert(id.parse().map_err(|_| bad())?, name.to_string());
+                    }
+                    ["lost", hart, count] => current.lost.push((
+                        hart.parse().map_err(|_| bad())?,
+                        count.parse().map_err(|_| bad())?,
+                    )),
+                    [hart, words @ ..] if words.len() == 4 => {
+                        let mut record = [0; 4];
+                        for (word, field) in record.iter_mut().zip(words) {
+                            *word = u64::from_str_radix(field, 16).map_err(|_| bad())?;
+                        }
+                        current.records.push((hart.parse().map_err(|_| bad())?, record));
+                    }
+                    _ => return Err(bad()),
+                }
+            }
+        }
+    }
+    let dump =
+        dump.ok_or_else(|| anyhow::anyhow!("[ ERROR ] no complete trace dump found in the log"))?;
+    for (hart, count) in &dump.lost {
+        eprintln!("[ WARN ] hart {}: {} older events were overwritten", hart, count);
+    }
+    Ok(render(&dump))
+}
+
+fn render(dump: &Dump) -> String {
+    let start = dump.records.iter().map(|(_, record)| record[0]).min().unwrap_or(0);
+    let micros = |ticks: u64| (ticks - start) as f64 * 1e6 / dump.timebase.max(1) as f64;
+
+    let mut events = Vec::new();
+    let mut harts: Vec<u64> = dump.records.iter().map(|(hart, _)| *hart).collect();
+    harts.sort();
+    harts.dedup();
+    for hart in harts {
+        events.push(format!(
+            r#"{{"name":"thread_name","ph":"M","pid":0,"tid":{hart},"args":{{"name":"hart {hart}"}}}}"#
+        ));
+    }
+    for (hart, [timestamp, id, arg0, arg1]) in &dump.records {
+        let event = id & 0xffff;
+        let name = match dump.names.get(&event) {
+            Some(name) => name.clone(),
+            None => format!("event {}", event),
+        };
+        let phase = match id >> 16 {
+            1 => r#""ph":"B""#,
+            2 => r#""ph":"E""#,
+            _ => r#""ph":"i","s":"t""#,
+        };
+        events.push(format!(
+            r#"{{"name":"{}",{},"ts":{:.3},"pid":0,"tid":{},"args":{{"arg0":{},"arg1":{}}}}}"#,
+            escape(&name),
+            phase,
+            micros(*timestamp),
+            hart,
+            arg0,
+            arg1
+        ));
+    }
+    format!("{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ns\"}}\n", events.join(",\n"))
+}
+
+fn escape(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '"' | '\\' => vec!['\\', c],
+            c if c.is_control() => format!("\\u{:04x}", c as u32).chars().collect(),
+            c => vec![c],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "\
+[    1.000000] booting\r
+[trace] begin 10000000\r
+[trace] event 0 trap\r
+[trace] event 1 ipi_send\r
+[trace] lost 1 5\r
+[trace] 0 64 10000 8 0\r
+[trace] 0 6e 20000 0 0\r
+[trace] 1 78 1 3 2\r
+[trace] end\r
+";
+
+    #[test]
+    fn decodes_records() {
+        let json = decode(DUMP).unwrap();
+        assert!(json.contains(r#""name":"hart 0""#));
+        assert!(json.contains(r#""name":"hart 1""#));
+        assert!(json.contains(
+            r#"{"name":"trap","ph":"B","ts":0.000,"pid":0,"tid":0,"args":{"arg0":8,"arg1":0}}"#
+        ));
+        // 10 ticks at 10 MHz
+        assert!(json.contains(r#"{"name":"trap","ph":"E","ts":1.000,"#));
+        assert!(json.contains(r#"{"name":"ipi_send","ph":"i","s":"t","ts":2.000,"pid":0,"tid":1,"args":{"arg0":3,"arg1":2}}"#));
+    }
+
+    #[test]
+    fn last_complete_dump_wins() {
+        let second = "[trace] begin 1000\n[trace] event 4 mark\n[trace] 2 5 4 1 0\n[trace] end\n";
+        // A dump cut off by a reset is ignored
+        let partial = "[trace] begin 1000\n[trace] 3 5 4 1 0\n";
+        let json = decode(&format!("{}{}{}", DUMP, second, partial)).unwrap();
+        assert!(json.contains(r#""name":"mark""#));
+        assert!(json.contains(r#""tid":2"#));
+        assert!(!json.contains("trap"));
+        assert!(!json.contains(r#""tid":3"#));
+    }
+
+    #[test]
+    fn unknown_event_keeps_its_id() {
+        let json = decode("[trace] begin 1\n[trace] 0 0 7 0 0\n[trace] end\n").unwrap();
+        assert!(json.contains(r#""name":"event 7""#));
+    }
+
+    #[test]
+    fn rejects_malformed_and_missing_dumps() {
+        assert!(decode("[trace] begin 1\n[trace] 0 zz 0 0 0\n[trace] end\n").is_err());
+        assert!(decode("[trace] begin 1\n[trace] 0 1 2\n[trace] end\n").is_err());
+        assert!(decode("[trace] begin 1\n[trace] 0 1 0 0 0\n").is_err());
+        assert!(decode("no trace here\n").is_err());
+    }
+
+    #[test]
+    fn escapes_names() {
+        assert_eq!(escape("a\"b\\c\n"), "a\\\"b\\\\c\\u000a");
+    }
+}